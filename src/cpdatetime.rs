@@ -1,124 +1,505 @@
-use chrono::{DateTime, FixedOffset};
 use std::fmt;
-use serde::{Deserialize, Serialize};
-use serde::{de, Deserializer, Serializer};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use crate::error::Error;
 
-#[derive(Debug, PartialEq)]
-pub struct CPDateTime(pub DateTime<FixedOffset>);
 pub(crate) const CP_DATETIME_NEWTYPE_STRUCT: &str = "CPDateTime";
 
-impl From<DateTime<FixedOffset>> for CPDateTime {
-    fn from(dt: DateTime<FixedOffset>) -> Self {
-        CPDateTime(dt)
+const SHV_EPOCH_MSEC: i64 = 1517529600000;
+
+#[cfg(feature = "chrono")]
+pub use self::chrono::CPDateTime;
+
+/// Packs milliseconds since the Unix epoch and a UTC offset in minutes
+/// into the ChainPack DateTime integer.
+pub(crate) fn encode_raw(epoch_msec: i64, offset_minutes: i32) -> i64 {
+    let mut val = epoch_msec - SHV_EPOCH_MSEC;
+    let mut has_tz = false;
+    let mut no_msec = false;
+
+    if val.rem_euclid(1000) == 0 {
+        val /= 1000;
+        no_msec = true;
+    }
+
+    if offset_minutes != 0 {
+        val <<= 7;
+        let tz_offset = (offset_minutes / 15) as i64;
+        val |= tz_offset & 0x7f;
+        has_tz = true;
+    }
+
+    val <<= 2;
+    if has_tz {
+        val |= 1;
+    }
+    if no_msec {
+        val |= 2;
+    }
+    val
+}
+
+/// Inverse of [`encode_raw`], returns milliseconds since the Unix epoch
+/// and the UTC offset in minutes. Fails for times out of `i64` milliseconds.
+pub(crate) fn decode_raw(v: i64) -> Result<(i64, i32), Error> {
+    let has_tz = v & 1 != 0;
+    let no_msec = v & 2 != 0;
+    let mut val = v >> 2;
+
+    let offset_minutes = if has_tz {
+        let tz_offset = (val & 0x7f) as i32;
+        val >>= 7;
+        if tz_offset & 0x40 != 0 {
+            (tz_offset | !0x7f) * 15
+        } else {
+            tz_offset * 15
+        }
+    } else {
+        0
+    };
+
+    let msecs = if no_msec {
+        val.checked_mul(1000)
+    } else {
+        Some(val)
+    };
+
+    let epoch_msec = msecs.and_then(|msecs| msecs.checked_add(SHV_EPOCH_MSEC)).ok_or(Error::InvalidDateTime)?;
+    Ok((epoch_msec, offset_minutes))
+}
+
+/// Reads the raw ChainPack DateTime integer, the backends convert it.
+struct RawDateTimeVisitor;
+
+impl<'de> de::Visitor<'de> for RawDateTimeVisitor {
+    type Value = i64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a ChainPack DateTime")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        deserializer.deserialize_i64(self)
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: de::Error
+    {
+        Ok(v)
     }
 }
 
-impl From<CPDateTime> for DateTime<FixedOffset> {
-    fn from(val: CPDateTime) -> Self {
-        val.0
+/// ChainPack DateTime as it is encoded, usable without a date/time backend.
+/// The `chrono` and `time` features add conversions from and to their types.
+///
+/// Like [`CPDateTime`], comparison, ordering and hashing go by the instant only.
+#[derive(Debug, Clone, Copy)]
+pub struct RawDateTime {
+    /// Milliseconds since the Unix epoch.
+    pub epoch_msec: i64,
+    /// UTC offset in minutes, written in whole quarter hours.
+    pub offset_minutes: i32,
+}
+
+impl PartialEq for RawDateTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.epoch_msec == other.epoch_msec
     }
 }
 
-const SHV_EPOCH_MSEC: i64 = 1517529600000;
+impl Eq for RawDateTime {}
+
+impl PartialOrd for RawDateTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RawDateTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch_msec.cmp(&other.epoch_msec)
+    }
+}
+
+impl Hash for RawDateTime {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.epoch_msec.hash(state);
+    }
+}
 
-impl Serialize for CPDateTime {
+impl Serialize for RawDateTime {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
-        S: Serializer
+        S: Serializer,
+    {
+        let val = encode_raw(self.epoch_msec, self.offset_minutes);
+        serializer.serialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, &val)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawDateTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
     {
-        let mut val = self.0.timestamp_millis() - SHV_EPOCH_MSEC;
-        let mut has_tz = false;
-        let mut no_msec = false;
+        let v = deserializer.deserialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, RawDateTimeVisitor)?;
+        let (epoch_msec, offset_minutes) = decode_raw(v).map_err(de::Error::custom)?;
+        Ok(RawDateTime { epoch_msec, offset_minutes })
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono {
+    use ::chrono::{DateTime, FixedOffset, Local, NaiveDateTime, SecondsFormat, Timelike, Utc};
+    use std::cmp::Ordering;
+    use std::fmt;
+    use std::hash::{Hash, Hasher};
+    use std::str::FromStr;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use serde::{Deserialize, Serialize};
+    use serde::{de, Deserializer, Serializer};
+
+    use crate::error::Error;
+    use super::{decode_raw, encode_raw, RawDateTime, RawDateTimeVisitor, CP_DATETIME_NEWTYPE_STRUCT};
+
+    /// Point in time with a UTC offset.
+    ///
+    /// Comparison, ordering and hashing go by the instant only, so the same
+    /// moment in two time zones is equal even though it is encoded differently.
+    ///
+    /// ChainPack keeps milliseconds, the conversions truncate finer precision
+    /// and comparisons ignore it when the field was set directly.
+    ///
+    /// Needs the default `chrono` feature, the `time` feature offers
+    /// `cpdatetime::time` instead.
+    #[derive(Debug, Clone, Copy)]
+    pub struct CPDateTime(pub DateTime<FixedOffset>);
 
-        if self.0.timestamp_subsec_millis() == 0 {
-            val /= 1000;
-            no_msec = true;
+    impl CPDateTime {
+        /// Current time in the local time zone.
+        pub fn now() -> Self {
+            CPDateTime::from(Local::now())
         }
 
-        let offset_minutes = self.0.offset().local_minus_utc() / 60;
-        if offset_minutes != 0 {
-            val <<= 7;
-            let tz_offset = (offset_minutes / 15) as i64;
-            val |= tz_offset & 0x7f;
-            has_tz = true;
+        /// UTC date/time `msec` milliseconds after the Unix epoch.
+        pub fn from_epoch_msec(msec: i64) -> Result<Self, Error> {
+            DateTime::from_timestamp_millis(msec).map(CPDateTime::from).ok_or(Error::InvalidDateTime)
         }
 
-        val <<= 2;
-        if has_tz {
-            val |= 1;
+        /// Milliseconds since the Unix epoch.
+        pub fn epoch_msec(&self) -> i64 {
+            self.0.timestamp_millis()
         }
-        if no_msec {
-            val |= 2;
+    }
+
+    impl PartialEq for CPDateTime {
+        fn eq(&self, other: &Self) -> bool {
+            self.epoch_msec() == other.epoch_msec()
         }
-        serializer.serialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, &val)
     }
-}
 
-impl<'de> Deserialize<'de> for CPDateTime {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-        where
-            D: Deserializer<'de>,
-    {
-        deserializer.deserialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, DateTimeVisitor).map(CPDateTime)
+    impl Eq for CPDateTime {}
+
+    impl PartialOrd for CPDateTime {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
     }
-}
 
-struct DateTimeVisitor;
+    impl Ord for CPDateTime {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.epoch_msec().cmp(&other.epoch_msec())
+        }
+    }
 
-impl<'de> de::Visitor<'de> for DateTimeVisitor {
-    type Value = DateTime<FixedOffset>;
+    impl Hash for CPDateTime {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.epoch_msec().hash(state);
+        }
+    }
 
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a ChainPack DateTime")
+    impl From<DateTime<FixedOffset>> for CPDateTime {
+        fn from(dt: DateTime<FixedOffset>) -> Self {
+            // never fails, a leap second still is one after truncation
+            let nsec = dt.nanosecond();
+            CPDateTime(dt.with_nanosecond(nsec - nsec % 1_000_000).unwrap_or(dt))
+        }
     }
 
-    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    impl From<CPDateTime> for DateTime<FixedOffset> {
+        fn from(val: CPDateTime) -> Self {
+            val.0
+        }
+    }
+
+    impl From<DateTime<Utc>> for CPDateTime {
+        fn from(dt: DateTime<Utc>) -> Self {
+            CPDateTime::from(dt.fixed_offset())
+        }
+    }
+
+    impl From<CPDateTime> for DateTime<Utc> {
+        fn from(val: CPDateTime) -> Self {
+            val.0.to_utc()
+        }
+    }
+
+    impl From<DateTime<Local>> for CPDateTime {
+        fn from(dt: DateTime<Local>) -> Self {
+            CPDateTime::from(dt.fixed_offset())
+        }
+    }
+
+    impl From<CPDateTime> for DateTime<Local> {
+        fn from(val: CPDateTime) -> Self {
+            val.0.with_timezone(&Local)
+        }
+    }
+
+    /// The naive date/time is taken as UTC.
+    impl From<NaiveDateTime> for CPDateTime {
+        fn from(dt: NaiveDateTime) -> Self {
+            CPDateTime::from(dt.and_utc())
+        }
+    }
+
+    /// The UTC date/time, the offset is dropped.
+    impl From<CPDateTime> for NaiveDateTime {
+        fn from(val: CPDateTime) -> Self {
+            val.0.naive_utc()
+        }
+    }
+
+    impl TryFrom<SystemTime> for CPDateTime {
+        type Error = Error;
+
+        fn try_from(t: SystemTime) -> Result<Self, Self::Error> {
+            let (sec, nsec) = match t.duration_since(UNIX_EPOCH) {
+                Ok(dur) => (i64::try_from(dur.as_secs()).map_err(|_| Error::InvalidDateTime)?, dur.subsec_nanos()),
+                Err(e) => {
+                    let dur = e.duration();
+                    let sec = i64::try_from(dur.as_secs()).map_err(|_| Error::InvalidDateTime)?;
+                    match dur.subsec_nanos() {
+                        0 => (-sec, 0),
+                        nsec => (-sec - 1, 1_000_000_000 - nsec),
+                    }
+                }
+            };
+            DateTime::from_timestamp(sec, nsec).map(CPDateTime::from).ok_or(Error::InvalidDateTime)
+        }
+    }
+
+    impl From<CPDateTime> for SystemTime {
+        fn from(val: CPDateTime) -> Self {
+            val.0.into()
+        }
+    }
+
+    impl From<CPDateTime> for RawDateTime {
+        fn from(val: CPDateTime) -> Self {
+            RawDateTime { epoch_msec: val.epoch_msec(), offset_minutes: val.0.offset().local_minus_utc() / 60 }
+        }
+    }
+
+    impl TryFrom<RawDateTime> for CPDateTime {
+        type Error = Error;
+
+        fn try_from(raw: RawDateTime) -> Result<Self, Self::Error> {
+            let offset = raw.offset_minutes.checked_mul(60).and_then(FixedOffset::east_opt).ok_or(Error::InvalidDateTime)?;
+            let utc = DateTime::from_timestamp_millis(raw.epoch_msec).ok_or(Error::InvalidDateTime)?;
+            Ok(CPDateTime(utc.with_timezone(&offset)))
+        }
+    }
+
+    /// RFC 3339, e.g. `2018-02-02T01:00:00.001+01:00` or `2018-02-02T00:00:00Z`.
+    impl fmt::Display for CPDateTime {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(&self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }
+    }
+
+    impl FromStr for CPDateTime {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            DateTime::parse_from_rfc3339(s).map(CPDateTime::from).map_err(|_| Error::InvalidDateTime)
+        }
+    }
+
+    impl Serialize for CPDateTime {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
-            D: Deserializer<'de>,
+            S: Serializer
+        {
+            let offset_minutes = self.0.offset().local_minus_utc() / 60;
+            let val = encode_raw(self.0.timestamp_millis(), offset_minutes);
+            serializer.serialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, &val)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for CPDateTime {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+        {
+            let v = deserializer.deserialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, RawDateTimeVisitor)?;
+            let (final_msecs, offset_minutes) = decode_raw(v).map_err(de::Error::custom)?;
+            let offset_secs = offset_minutes * 60;
+            let naive_dt = DateTime::from_timestamp_millis(final_msecs)
+                .ok_or_else(|| de::Error::custom(format!("invalid timestamp milliseconds: {}", final_msecs)))?;
+            let offset = FixedOffset::east_opt(offset_secs)
+                .ok_or_else(|| de::Error::custom(format!("invalid timezone offset: {}", offset_secs)))?;
+            Ok(CPDateTime(naive_dt.with_timezone(&offset)))
+        }
+    }
+}
+
+/// Serde helpers writing a `time::OffsetDateTime` as ChainPack DateTime, for
+/// use with `#[serde(with = "serde_chainpack::cpdatetime::time")]`.
+#[cfg(feature = "time")]
+pub mod time {
+    use ::time::{OffsetDateTime, UtcOffset};
+    use serde::{de, Deserializer, Serializer};
+
+    use crate::error::Error;
+    use super::{decode_raw, encode_raw, RawDateTime, RawDateTimeVisitor, CP_DATETIME_NEWTYPE_STRUCT};
+
+    pub fn serialize<S>(value: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
     {
-        deserializer.deserialize_i64(self)
+        let epoch_msec = value.unix_timestamp_nanos().div_euclid(1_000_000) as i64;
+        let val = encode_raw(epoch_msec, value.offset().whole_minutes() as i32);
+        serializer.serialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, &val)
     }
 
-    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
-        where
-            E: de::Error
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
     {
-        let has_tz = v & 1 != 0;
-        let no_msec = v & 2 != 0;
-        let mut val = v >> 2;
-
-        let offset_secs = if has_tz {
-            let tz_offset = (val & 0x7f) as i32;
-            val >>= 7;
-            if tz_offset & 0x40 != 0 {
-                (tz_offset | !0x7f) * 15 * 60
-            } else {
-                tz_offset * 15 * 60
+        let v = deserializer.deserialize_newtype_struct(CP_DATETIME_NEWTYPE_STRUCT, RawDateTimeVisitor)?;
+        let (epoch_msec, offset_minutes) = decode_raw(v).map_err(de::Error::custom)?;
+        let offset = UtcOffset::from_whole_seconds(offset_minutes * 60)
+            .map_err(|_| de::Error::custom(format!("invalid timezone offset: {}", offset_minutes * 60)))?;
+        OffsetDateTime::from_unix_timestamp_nanos(epoch_msec as i128 * 1_000_000)
+            .ok()
+            .and_then(|dt| dt.checked_to_offset(offset))
+            .ok_or_else(|| de::Error::custom(format!("invalid timestamp milliseconds: {}", epoch_msec)))
+    }
+
+    impl From<OffsetDateTime> for RawDateTime {
+        fn from(dt: OffsetDateTime) -> Self {
+            let epoch_msec = dt.unix_timestamp_nanos().div_euclid(1_000_000) as i64;
+            RawDateTime { epoch_msec, offset_minutes: dt.offset().whole_minutes() as i32 }
+        }
+    }
+
+    impl TryFrom<RawDateTime> for OffsetDateTime {
+        type Error = Error;
+
+        fn try_from(raw: RawDateTime) -> Result<Self, Self::Error> {
+            let offset = raw.offset_minutes.checked_mul(60)
+                .and_then(|secs| UtcOffset::from_whole_seconds(secs).ok())
+                .ok_or(Error::InvalidDateTime)?;
+            OffsetDateTime::from_unix_timestamp_nanos(raw.epoch_msec as i128 * 1_000_000)
+                .ok()
+                .and_then(|dt| dt.checked_to_offset(offset))
+                .ok_or(Error::InvalidDateTime)
+        }
+    }
+
+    /// Fails only for the years beyond chrono's range that time's
+    /// `large-dates` feature allows.
+    #[cfg(feature = "chrono")]
+    impl TryFrom<OffsetDateTime> for super::CPDateTime {
+        type Error = Error;
+
+        fn try_from(dt: OffsetDateTime) -> Result<Self, Self::Error> {
+            let utc = ::chrono::DateTime::from_timestamp(dt.unix_timestamp(), dt.nanosecond()).ok_or(Error::InvalidDateTime)?;
+            let offset = ::chrono::FixedOffset::east_opt(dt.offset().whole_seconds()).ok_or(Error::InvalidDateTime)?;
+            Ok(super::CPDateTime::from(utc.with_timezone(&offset)))
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    impl TryFrom<super::CPDateTime> for OffsetDateTime {
+        type Error = Error;
+
+        fn try_from(val: super::CPDateTime) -> Result<Self, Self::Error> {
+            let nanos = val.0.timestamp() as i128 * 1_000_000_000 + val.0.timestamp_subsec_nanos() as i128;
+            let offset = UtcOffset::from_whole_seconds(val.0.offset().local_minus_utc()).map_err(|_| Error::InvalidDateTime)?;
+            OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                .ok()
+                .and_then(|dt| dt.checked_to_offset(offset))
+                .ok_or(Error::InvalidDateTime)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use ::time::{OffsetDateTime, UtcOffset};
+        use serde::{Deserialize, Serialize};
+        use crate::{cpon, de::from_slice, ser::to_vec, types::CP_DATETIME};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Event {
+            #[serde(with = "crate::cpdatetime::time")]
+            timestamp: OffsetDateTime,
+        }
+
+        fn datetime(epoch_msec: i64, offset_minutes: i32) -> OffsetDateTime {
+            OffsetDateTime::from_unix_timestamp_nanos(epoch_msec as i128 * 1_000_000).unwrap()
+                .to_offset(UtcOffset::from_whole_seconds(offset_minutes * 60).unwrap())
+        }
+
+        #[test]
+        fn test_time_serde() {
+            let test_cases = vec![
+                (datetime(0, 0), vec![CP_DATETIME, 0b11110001, 0b10000001, 0b01101001, 0b11001110, 0b10100111, 0b11111110]),
+                (datetime(1517529600001, 60), vec![CP_DATETIME, 0b10000010, 0b00010001]),
+                (datetime(2246004900123, -615), vec![CP_DATETIME, 0b11110011, 0b00000001, 0b01010011, 0b00111001, 0b00000101, 0b11100010, 0b00110111, 0b01011101]),
+            ];
+            for (timestamp, expected) in test_cases {
+                let event = Event { timestamp };
+                let serialized = to_vec(&event).unwrap();
+                assert_eq!(serialized[serialized.len() - expected.len() - 1..serialized.len() - 1], expected[..]);
+                let deserialized: Event = from_slice(&serialized).unwrap();
+                assert_eq!(deserialized, event);
+                assert_eq!(deserialized.timestamp.offset(), timestamp.offset());
             }
-        } else {
-            0
-        };
 
-        let msecs = if no_msec {
-            val * 1000
-        } else {
-            val
-        };
+            let event = Event { timestamp: datetime(1517529600001, 60) };
+            let text = cpon::to_string(&event).unwrap();
+            assert_eq!(text, r#"{"timestamp":d"2018-02-02T01:00:00.001+01"}"#);
+            assert_eq!(cpon::from_str::<Event>(&text).unwrap(), event);
+        }
+
+        #[cfg(feature = "chrono")]
+        #[test]
+        fn test_time_chrono_conversions() {
+            use crate::cpdatetime::CPDateTime;
 
-        let final_msecs = msecs + SHV_EPOCH_MSEC;
-        let naive_dt = DateTime::from_timestamp(final_msecs / 1000, (final_msecs.rem_euclid(1000) * 1_000_000) as u32)
-            .ok_or_else(|| de::Error::custom(format!("invalid timestamp milliseconds: {}", final_msecs)))?;
-        let offset = FixedOffset::east_opt(offset_secs)
-            .ok_or_else(|| de::Error::custom(format!("invalid timezone offset: {}", offset_secs)))?;
-        Ok(naive_dt.with_timezone(&offset))
+            let dt: CPDateTime = "2041-03-04T00:00:00.123-10:15".parse().unwrap();
+            let odt = OffsetDateTime::try_from(dt).unwrap();
+            assert_eq!(odt, datetime(2246004900123, -615));
+            assert_eq!(odt.offset(), UtcOffset::from_whole_seconds(-615 * 60).unwrap());
+            assert_eq!(CPDateTime::try_from(odt).unwrap().to_string(), "2041-03-04T00:00:00.123-10:15");
+            // year 20983 is beyond time's default range
+            assert!(OffsetDateTime::try_from(CPDateTime::from_epoch_msec(600_000_000_000_000).unwrap()).is_err());
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "chrono"))]
 mod tests {
     use chrono::DateTime;
     use serde::{Deserialize, Serialize};
-    use crate::{de::from_slice, ser::tests::to_vec, types::CP_DATETIME};
+    use crate::{de::from_slice, ser::to_vec, types::CP_DATETIME};
     use super::CPDateTime;
 
     #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -169,4 +550,66 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_datetime_conversions() {
+        use std::collections::BTreeSet;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        use chrono::{Local, NaiveDateTime, Utc};
+        use crate::error::Error;
+
+        let dt: CPDateTime = "2018-02-02T01:00:00.001+01:00".parse().unwrap();
+        assert_eq!(dt.to_string(), "2018-02-02T01:00:00.001+01:00");
+        assert_eq!(dt.epoch_msec(), 1517529600001);
+        assert_eq!(CPDateTime::from_epoch_msec(1517529600001).unwrap(), dt);
+        assert_eq!(CPDateTime::from_epoch_msec(0).unwrap().to_string(), "1970-01-01T00:00:00Z");
+        assert!(matches!(CPDateTime::from_epoch_msec(i64::MAX), Err(Error::InvalidDateTime)));
+        assert!(matches!("2018-02-02".parse::<CPDateTime>(), Err(Error::InvalidDateTime)));
+
+        let utc: chrono::DateTime<Utc> = dt.into();
+        assert_eq!(utc.to_rfc3339(), "2018-02-02T00:00:00.001+00:00");
+        assert_eq!(CPDateTime::from(utc), dt);
+        let local: chrono::DateTime<Local> = dt.into();
+        assert_eq!(CPDateTime::from(local), dt);
+        let naive: NaiveDateTime = dt.into();
+        assert_eq!(naive.to_string(), "2018-02-02 00:00:00.001");
+        assert_eq!(CPDateTime::from(naive), dt);
+        let system: SystemTime = dt.into();
+        assert_eq!(system, UNIX_EPOCH + Duration::from_millis(1517529600001));
+        assert_eq!(CPDateTime::try_from(system).unwrap(), dt);
+        let before_epoch = UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(CPDateTime::try_from(before_epoch).unwrap().to_string(), "1969-12-31T23:59:58.500Z");
+
+        // times before the epoch keep their second too
+        for msec in [-1500, -1000, -1, -86_400_001] {
+            let dt = CPDateTime::from_epoch_msec(msec).unwrap();
+            let deserialized = from_slice::<CPDateTime>(&to_vec(&dt).unwrap()).unwrap();
+            assert_eq!(deserialized.epoch_msec(), msec);
+            assert_eq!(deserialized.to_string(), dt.to_string());
+        }
+        assert_eq!(from_slice::<CPDateTime>(&to_vec(&CPDateTime::from_epoch_msec(-1500).unwrap()).unwrap()).unwrap().to_string(), "1969-12-31T23:59:58.500Z");
+
+        let now = CPDateTime::now();
+        assert_eq!(from_slice::<CPDateTime>(&to_vec(&now).unwrap()).unwrap().0, now.0);
+
+        // every constructor drops what ChainPack can't keep
+        let fine = "2018-02-02T01:00:00.001999999+01:00";
+        let parsed: CPDateTime = fine.parse().unwrap();
+        assert_eq!(parsed.0, dt.0);
+        assert_eq!(CPDateTime::from(DateTime::parse_from_rfc3339(fine).unwrap()).0, dt.0);
+        assert_eq!(CPDateTime::from(DateTime::parse_from_rfc3339(fine).unwrap().to_utc()).0, dt.0);
+        assert_eq!(CPDateTime::from(DateTime::parse_from_rfc3339(fine).unwrap().naive_utc()).0, dt.0);
+        let system = UNIX_EPOCH + Duration::from_nanos(1517529600001999999);
+        assert_eq!(CPDateTime::try_from(system).unwrap().0, dt.0);
+        let before_epoch = UNIX_EPOCH - Duration::from_nanos(1_499_999_999);
+        assert_eq!(CPDateTime::try_from(before_epoch).unwrap().to_string(), "1969-12-31T23:59:58.500Z");
+        let unset = CPDateTime(DateTime::parse_from_rfc3339(fine).unwrap());
+        assert_eq!(unset, dt);
+        assert_eq!(from_slice::<CPDateTime>(&to_vec(&unset).unwrap()).unwrap().0, dt.0);
+
+        // same instant in another zone is equal and sorts together
+        let earlier: CPDateTime = "2018-02-02T00:00:00Z".parse().unwrap();
+        let set: BTreeSet<_> = [dt, earlier, CPDateTime::from(utc)].into_iter().collect();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [earlier, dt]);
+    }
 }
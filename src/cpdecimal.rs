@@ -1,14 +1,18 @@
 use serde::de::{IntoDeserializer, SeqAccess};
 use serde::{Deserialize, Serialize};
 use serde::{de, Deserializer, Serializer};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use crate::error::Error;
-use crate::rawbytes::CP_RAWBYTES_NEWTYPE_STRUCT;
-use crate::ser::serialize_raw_i64;
-use crate::types::CP_DECIMAL;
 
-#[derive(Debug, PartialEq)]
+/// Decimal number `mantissa * 10^exponent`.
+///
+/// Comparison and hashing go by value, so `1.0` and `1` are equal even though
+/// they are encoded differently.
+#[derive(Debug, Clone, Copy)]
 pub struct CPDecimal {
     mantissa: i64,
     exponent: i8,
@@ -28,6 +32,267 @@ impl CPDecimal {
     pub fn to_f64(&self) -> f64 {
         self.mantissa as f64 * 10f64.powi(self.exponent as i32)
     }
+
+    /// Shortest decimal that reads back as the same `f64`, so `0.1` becomes
+    /// `1e-1` rather than the exact binary value.
+    pub fn from_f64(value: f64) -> Result<Self, Error> {
+        let (mantissa, exponent) = shortest_digits(value)?;
+        Self::from_wide(mantissa as i128, exponent as i64).ok_or(Error::DecimalOutOfRange("CPDecimal"))
+    }
+
+    /// Decimal with `scale` fractional digits, the shortest form of `value`
+    /// is rounded half away from zero. For other rounding modes use
+    /// [`from_f64`](Self::from_f64) followed by [`rescale`](Self::rescale).
+    pub fn from_f64_with_scale(value: f64, scale: i8) -> Result<Self, Error> {
+        let exponent = -(scale as i32);
+        let exponent = i8::try_from(exponent).map_err(|_| Error::DecimalExponentOutOfRange(exponent as i64))?;
+        let (mantissa, e) = shortest_digits(value)?;
+        let mantissa = if e >= exponent as i32 {
+            10i128.checked_pow((e - exponent as i32) as u32).and_then(|p| p.checked_mul(mantissa as i128))
+        } else {
+            Some(round_div(mantissa as i128, (exponent as i32 - e) as u32, RoundingMode::HalfUp))
+        };
+        let mantissa = mantissa.and_then(|m| i64::try_from(m).ok()).ok_or(Error::DecimalOutOfRange("CPDecimal"))?;
+        Ok(Self { mantissa, exponent })
+    }
+
+    /// Same value with the trailing zeros of the mantissa moved to the exponent,
+    /// zero becomes `0e0`.
+    pub fn normalize(&self) -> Self {
+        if self.mantissa == 0 {
+            return Self::new(0, 0);
+        }
+        let (mut mantissa, mut exponent) = (self.mantissa, self.exponent);
+        while mantissa % 10 == 0 && exponent < i8::MAX {
+            mantissa /= 10;
+            exponent += 1;
+        }
+        Self { mantissa, exponent }
+    }
+
+    /// Same value with a smaller or equal exponent, `None` if the mantissa overflows.
+    fn with_exponent(&self, exponent: i8) -> Option<Self> {
+        let shift = (self.exponent as i32 - exponent as i32) as u32;
+        let mantissa = match self.mantissa {
+            0 => 0,
+            m => 10i64.checked_pow(shift)?.checked_mul(m)?,
+        };
+        Some(Self { mantissa, exponent })
+    }
+
+    /// Value with the given exponent, digits below it are rounded away by `mode`.
+    /// Returns `None` if a smaller exponent makes the mantissa overflow.
+    pub fn rescale(&self, exponent: i8, mode: RoundingMode) -> Option<Self> {
+        if exponent <= self.exponent {
+            return self.with_exponent(exponent);
+        }
+        let shift = (exponent as i32 - self.exponent as i32) as u32;
+        let mantissa = round_div(self.mantissa as i128, shift, mode) as i64;
+        Some(Self { mantissa, exponent })
+    }
+
+    /// Exact sum, `None` if it doesn't fit.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let exponent = self.exponent.min(other.exponent);
+        let (a, b) = (self.with_exponent(exponent)?, other.with_exponent(exponent)?);
+        Some(Self { mantissa: a.mantissa.checked_add(b.mantissa)?, exponent })
+    }
+
+    /// Exact difference, `None` if it doesn't fit.
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let exponent = self.exponent.min(other.exponent);
+        let (a, b) = (self.with_exponent(exponent)?, other.with_exponent(exponent)?);
+        Some(Self { mantissa: a.mantissa.checked_sub(b.mantissa)?, exponent })
+    }
+
+    /// Exact product, `None` if it doesn't fit.
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let exponent = self.exponent.checked_add(other.exponent)?;
+        Some(Self { mantissa, exponent })
+    }
+
+    /// Fits `mantissa * 10^exponent` exactly, moving trailing zeros between
+    /// the mantissa and the exponent where that helps.
+    fn from_wide(mut mantissa: i128, mut exponent: i64) -> Option<Self> {
+        if mantissa == 0 {
+            return Some(Self::new(0, exponent.clamp(i8::MIN as i64, i8::MAX as i64) as i8));
+        }
+        while (i64::try_from(mantissa).is_err() || exponent < i8::MIN as i64) && mantissa % 10 == 0 {
+            mantissa /= 10;
+            exponent += 1;
+        }
+        while exponent > i8::MAX as i64 {
+            mantissa = mantissa.checked_mul(10)?;
+            exponent -= 1;
+        }
+        Some(Self::new(i64::try_from(mantissa).ok()?, i8::try_from(exponent).ok()?))
+    }
+}
+
+/// How [`CPDecimal::rescale`] rounds digits it drops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Towards zero, truncating.
+    Down,
+    /// Away from zero.
+    Up,
+    /// Towards negative infinity.
+    Floor,
+    /// Towards positive infinity.
+    Ceiling,
+    /// To the nearest, ties away from zero.
+    HalfUp,
+    /// To the nearest, ties towards zero.
+    HalfDown,
+    /// To the nearest, ties to an even digit.
+    HalfEven,
+}
+
+/// `m / 10^shift` rounded by `mode`.
+fn round_div(m: i128, shift: u32, mode: RoundingMode) -> i128 {
+    // beyond 10^38 the divisor is so much larger than any mantissa that
+    // i128::MAX stands in for it
+    let divisor = 10i128.checked_pow(shift).unwrap_or(i128::MAX);
+    let (q, r) = (m / divisor, m % divisor);
+    let away_from_zero = r != 0 && match mode {
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::Floor => m < 0,
+        RoundingMode::Ceiling => m > 0,
+        RoundingMode::HalfUp | RoundingMode::HalfDown | RoundingMode::HalfEven => {
+            match (2 * r.abs()).cmp(&divisor) {
+                Ordering::Less => false,
+                Ordering::Greater => true,
+                Ordering::Equal => match mode {
+                    RoundingMode::HalfUp => true,
+                    RoundingMode::HalfDown => false,
+                    _ => q % 2 != 0,
+                },
+            }
+        }
+    };
+    if away_from_zero { q + m.signum() } else { q }
+}
+
+/// Mantissa and exponent of the shortest decimal reading back as `value`.
+fn shortest_digits(value: f64) -> Result<(i64, i32), Error> {
+    if !value.is_finite() {
+        return Err(Error::NonFiniteFloat(value));
+    }
+    // `{:e}` prints the shortest round-trip digits, e.g. `-1.25e-3`
+    let s = format!("{value:e}");
+    let (digits, exponent) = s.split_once('e').ok_or(Error::InvalidDecimal)?;
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    let mantissa = format!("{int_part}{frac_part}").parse::<i64>().map_err(|_| Error::InvalidDecimal)?;
+    let exponent = exponent.parse::<i32>().map_err(|_| Error::InvalidDecimal)? - frac_part.len() as i32;
+    Ok((mantissa, exponent))
+}
+
+impl PartialEq for CPDecimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CPDecimal {}
+
+impl PartialOrd for CPDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CPDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let sign = self.mantissa.signum().cmp(&other.mantissa.signum());
+        if sign != Ordering::Equal || self.mantissa == 0 {
+            return sign;
+        }
+        // the order of magnitude decides, unless it's the same and the
+        // exponents are then at most 18 apart
+        let magnitude = |d: &Self| d.mantissa.unsigned_abs().ilog10() as i32 + d.exponent as i32;
+        let ord = magnitude(self).cmp(&magnitude(other)).then_with(|| {
+            let (a, b) = (self.mantissa.unsigned_abs() as u128, other.mantissa.unsigned_abs() as u128);
+            let shift = self.exponent as i32 - other.exponent as i32;
+            if shift >= 0 {
+                (a * 10u128.pow(shift as u32)).cmp(&b)
+            } else {
+                a.cmp(&(b * 10u128.pow(shift.unsigned_abs())))
+            }
+        });
+        if self.mantissa < 0 { ord.reverse() } else { ord }
+    }
+}
+
+impl Hash for CPDecimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa.hash(state);
+        normalized.exponent.hash(state);
+    }
+}
+
+/// Prints the exact value in plain notation, like `123.45` or `-0.001`.
+impl fmt::Display for CPDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // a zero keeps its scale, but not a row of zeros for a positive exponent
+        if self.mantissa == 0 && self.exponent >= 0 {
+            return f.write_str("0");
+        }
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        if self.exponent >= 0 {
+            write!(f, "{sign}{digits}{:0<1$}", "", self.exponent as usize)
+        } else {
+            let frac_len = self.exponent.unsigned_abs() as usize;
+            if digits.len() > frac_len {
+                let (int_part, frac_part) = digits.split_at(digits.len() - frac_len);
+                write!(f, "{sign}{int_part}.{frac_part}")
+            } else {
+                write!(f, "{sign}0.{digits:0>frac_len$}")
+            }
+        }
+    }
+}
+
+/// Parses plain and scientific notation, like `123.45`, `-1e-3` or `1.5E10`.
+/// The digits are kept exactly, so `1.50` has the mantissa 150.
+impl FromStr for CPDecimal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, exponent) = match s.split_once(['e', 'E']) {
+            Some((number, exponent)) => (number, exponent.parse::<i64>().map_err(|_| Error::InvalidDecimal)?),
+            None => (s, 0),
+        };
+        let (int_part, frac_part) = number.split_once('.').unwrap_or((number, ""));
+        let int_digits = int_part.strip_prefix(['-', '+']).unwrap_or(int_part);
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int_digits.len() + frac_part.len() == 0 || !is_digits(int_digits) || !is_digits(frac_part) {
+            return Err(Error::InvalidDecimal);
+        }
+        let exponent = exponent.checked_sub(frac_part.len() as i64).ok_or(Error::InvalidDecimal)?;
+        let (mantissa, exponent) = fit_mantissa(&format!("{int_part}{frac_part}"), exponent).ok_or(Error::InvalidDecimal)?;
+        let exponent = i8::try_from(exponent).map_err(|_| Error::InvalidDecimal)?;
+        Ok(CPDecimal { mantissa, exponent })
+    }
+}
+
+/// Parses the signed decimal `digits` scaled by `exponent`, moving trailing
+/// zeros to the exponent while the mantissa doesn't fit an `i64`, so the
+/// zeros written out for a large positive exponent read back.
+pub(crate) fn fit_mantissa(mut digits: &str, mut exponent: i64) -> Option<(i64, i64)> {
+    loop {
+        match digits.parse() {
+            Ok(mantissa) => return Some((mantissa, exponent)),
+            Err(_) if digits.ends_with('0') => {
+                digits = &digits[..digits.len() - 1];
+                exponent = exponent.checked_add(1)?;
+            }
+            Err(_) => return None,
+        }
+    }
 }
 
 impl Serialize for CPDecimal {
@@ -35,11 +300,7 @@ impl Serialize for CPDecimal {
     where
         S: Serializer,
     {
-        let mut bytes = vec![];
-        bytes.push(CP_DECIMAL);
-        serialize_raw_i64(&mut bytes, self.mantissa).map_err(serde::ser::Error::custom)?;
-        serialize_raw_i64(&mut bytes, self.exponent as i64).map_err(serde::ser::Error::custom)?;
-        serializer.serialize_newtype_struct(CP_RAWBYTES_NEWTYPE_STRUCT, &crate::rawbytes::RawBytes(&bytes))
+        serializer.serialize_newtype_struct(CP_DECIMAL_NEWTYPE_STRUCT, &(self.mantissa, self.exponent))
     }
 }
 
@@ -49,7 +310,7 @@ pub(crate) struct DecimalDeserializer {
     pub(crate) state: u8,
 }
 
-impl<'de, 'a> SeqAccess<'de> for &'a mut DecimalDeserializer {
+impl<'de> SeqAccess<'de> for &mut DecimalDeserializer {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> crate::error::Result<Option<T::Value>>
@@ -98,9 +359,153 @@ impl<'de> de::Visitor<'de> for DecimalVisitor {
     }
 }
 
+/// Serde helpers for a [`CPDecimal`] kept as a string, like a price in a
+/// config file, for use with `#[serde(with = "serde_chainpack::cpdecimal::as_string")]`.
+pub mod as_string {
+    use std::fmt;
+    use serde::{de, Deserializer, Serializer};
+    use super::CPDecimal;
+
+    pub fn serialize<S>(value: &CPDecimal, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<CPDecimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(DecimalStrVisitor)
+    }
+
+    struct DecimalStrVisitor;
+
+    impl de::Visitor<'_> for DecimalStrVisitor {
+        type Value = CPDecimal;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a decimal number string")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            v.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+    }
+}
+
+/// Conversions between [`CPDecimal`] and `rust_decimal::Decimal`, and serde
+/// helpers writing a `Decimal` as `CP_DECIMAL`, for use with
+/// `#[serde(with = "serde_chainpack::cpdecimal::rust_decimal")]`.
+#[cfg(feature = "rust_decimal")]
+pub mod rust_decimal {
+    use ::rust_decimal::Decimal;
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+    use crate::error::Error;
+    use super::CPDecimal;
+
+    impl TryFrom<CPDecimal> for Decimal {
+        type Error = Error;
+
+        fn try_from(value: CPDecimal) -> Result<Self, Self::Error> {
+            let out_of_range = || Error::DecimalOutOfRange("rust_decimal::Decimal");
+            let mut mantissa = value.mantissa as i128;
+            let mut exponent = value.exponent as i32;
+            if exponent < -(Decimal::MAX_SCALE as i32) {
+                let normalized = value.normalize();
+                (mantissa, exponent) = (normalized.mantissa as i128, normalized.exponent as i32);
+            }
+            if exponent > 0 && mantissa != 0 {
+                mantissa = 10i128.checked_pow(exponent as u32).and_then(|p| p.checked_mul(mantissa)).ok_or_else(out_of_range)?;
+            }
+            Decimal::try_from_i128_with_scale(mantissa, (-exponent).max(0) as u32).map_err(|_| out_of_range())
+        }
+    }
+
+    impl TryFrom<Decimal> for CPDecimal {
+        type Error = Error;
+
+        fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+            CPDecimal::from_wide(value.mantissa(), -(value.scale() as i64)).ok_or(Error::DecimalOutOfRange("CPDecimal"))
+        }
+    }
+
+    pub fn serialize<S>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        CPDecimal::try_from(*value).map_err(ser::Error::custom)?.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CPDecimal::deserialize(deserializer)?.try_into().map_err(de::Error::custom)
+    }
+}
+
+/// Conversions between [`CPDecimal`] and `bigdecimal::BigDecimal`, and serde
+/// helpers writing a `BigDecimal` as `CP_DECIMAL`, for use with
+/// `#[serde(with = "serde_chainpack::cpdecimal::bigdecimal")]`.
+#[cfg(feature = "bigdecimal")]
+pub mod bigdecimal {
+    use ::bigdecimal::{num_bigint::BigInt, BigDecimal};
+    use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+    use crate::error::Error;
+    use super::CPDecimal;
+
+    impl From<CPDecimal> for BigDecimal {
+        fn from(value: CPDecimal) -> Self {
+            BigDecimal::new(BigInt::from(value.mantissa), -(value.exponent as i64))
+        }
+    }
+
+    impl TryFrom<&BigDecimal> for CPDecimal {
+        type Error = Error;
+
+        fn try_from(value: &BigDecimal) -> Result<Self, Self::Error> {
+            let out_of_range = || Error::DecimalOutOfRange("CPDecimal");
+            let (digits, scale) = value.as_bigint_and_exponent();
+            let (digits, scale) = match i128::try_from(&digits) {
+                Ok(digits) => (digits, scale),
+                Err(_) => {
+                    let (digits, scale) = value.normalized().into_bigint_and_exponent();
+                    (i128::try_from(&digits).map_err(|_| out_of_range())?, scale)
+                }
+            };
+            CPDecimal::from_wide(digits, -scale).ok_or_else(out_of_range)
+        }
+    }
+
+    impl TryFrom<BigDecimal> for CPDecimal {
+        type Error = Error;
+
+        fn try_from(value: BigDecimal) -> Result<Self, Self::Error> {
+            CPDecimal::try_from(&value)
+        }
+    }
+
+    pub fn serialize<S>(value: &BigDecimal, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        CPDecimal::try_from(value).map_err(ser::Error::custom)?.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BigDecimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CPDecimal::deserialize(deserializer).map(BigDecimal::from)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{de::from_slice, types::CP_DECIMAL};
+    use serde::{Deserialize, Serialize};
+    use crate::{cpon, de::from_slice, error::Error, types::CP_DECIMAL};
     use super::CPDecimal;
 
     #[test]
@@ -111,11 +516,233 @@ mod tests {
         ];
         for (dec, expected) in test_cases {
             // println!("--------------------\nTesting: {dec:?}");
-            let serialized = crate::ser::tests::to_vec(&dec).expect("serialization failed");
+            let serialized = crate::ser::to_vec(&dec).expect("serialization failed");
             // println!("Serialized: {serialized:x?}");
             assert_eq!(expected, serialized);
             let deserialized: CPDecimal = from_slice(&serialized).expect("deserialization failed");
             assert_eq!(dec, deserialized);
         }
     }
+
+    #[test]
+    fn test_decimal_strings() {
+        let test_cases = [
+            ("123.45", CPDecimal::new(12345, -2)),
+            ("-1e-3", CPDecimal::new(-1, -3)),
+            ("1.5E10", CPDecimal::new(15, 9)),
+            ("+0.50", CPDecimal::new(50, -2)),
+            (".5", CPDecimal::new(5, -1)),
+            ("7", CPDecimal::new(7, 0)),
+        ];
+        for (s, dec) in test_cases {
+            assert_eq!(s.parse::<CPDecimal>().unwrap(), dec, "{s}");
+        }
+        for s in ["", "-", ".", "1e", "1.2.3", "1,5", " 1", "1e200", "99999999999999999999", "1.5e-9223372036854775808"] {
+            assert!(matches!(s.parse::<CPDecimal>(), Err(Error::InvalidDecimal)), "{s}");
+        }
+        // zeros beyond the i64 range go to the exponent, as in Cpon
+        let dec: CPDecimal = "150000000000000000000000.0".parse().unwrap();
+        assert_eq!((dec.mantissa(), dec.exponent()), (1_500_000_000_000_000_000, 5));
+        for dec in [CPDecimal::new(i64::MAX, i8::MAX), CPDecimal::new(-12, 100)] {
+            let parsed: CPDecimal = dec.to_string().parse().unwrap();
+            assert_eq!(parsed, dec);
+            let from_cpon = crate::cpon::from_str::<CPDecimal>(&crate::cpon::to_string(&dec).unwrap()).unwrap();
+            assert_eq!((from_cpon.mantissa(), from_cpon.exponent()), (parsed.mantissa(), parsed.exponent()));
+        }
+
+        assert_eq!(CPDecimal::new(12345, -2).to_string(), "123.45");
+        assert_eq!(CPDecimal::new(-5, -3).to_string(), "-0.005");
+        assert_eq!(CPDecimal::new(15, 9).to_string(), "15000000000");
+        assert_eq!(CPDecimal::new(i64::MIN, -1).to_string(), "-922337203685477580.8");
+        assert_eq!(CPDecimal::new(0, -2).to_string(), "0.00");
+        assert_eq!(CPDecimal::new(0, 3).to_string(), "0");
+    }
+
+    #[test]
+    fn test_decimal_as_string() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Tariff {
+            #[serde(with = "super::as_string")]
+            price: CPDecimal,
+        }
+
+        let tariff: Tariff = cpon::from_str(r#"{"price":"12.50"}"#).unwrap();
+        assert_eq!(tariff, Tariff { price: CPDecimal::new(1250, -2) });
+        assert_eq!(cpon::to_string(&tariff).unwrap(), r#"{"price":"12.50"}"#);
+        let serialized = crate::ser::to_vec(&tariff).unwrap();
+        assert_eq!(from_slice::<Tariff>(&serialized).unwrap(), tariff);
+        assert!(cpon::from_str::<Tariff>(r#"{"price":"12,50"}"#).is_err());
+        let free = Tariff { price: CPDecimal::new(0, -2) };
+        assert_eq!(cpon::to_string(&free).unwrap(), r#"{"price":"0.00"}"#);
+    }
+
+    #[test]
+    fn test_decimal_value_semantics() {
+        use std::collections::HashSet;
+        assert_eq!(CPDecimal::new(10, -1), CPDecimal::new(1, 0));
+        assert_eq!(CPDecimal::new(0, 5), CPDecimal::new(0, -5));
+        assert_eq!(CPDecimal::new(1200, -2).normalize(), CPDecimal::new(12, 0));
+        assert_eq!(CPDecimal::new(1200, -2).normalize().mantissa(), 12);
+        assert_eq!(CPDecimal::new(100, 126).normalize().exponent(), 127);
+        assert_eq!(CPDecimal::new(0, 5).normalize().exponent(), 0);
+
+        let mut sorted = vec![
+            CPDecimal::new(15, -1),
+            CPDecimal::new(-2, 0),
+            CPDecimal::new(1, 2),
+            CPDecimal::new(-25, -1),
+            CPDecimal::new(0, 0),
+            CPDecimal::new(149, -2),
+            CPDecimal::new(1, -128),
+            CPDecimal::new(i64::MAX, -100),
+        ];
+        sorted.sort();
+        assert_eq!(sorted, [
+            CPDecimal::new(-25, -1),
+            CPDecimal::new(-2, 0),
+            CPDecimal::new(0, 0),
+            CPDecimal::new(1, -128),
+            CPDecimal::new(i64::MAX, -100),
+            CPDecimal::new(149, -2),
+            CPDecimal::new(15, -1),
+            CPDecimal::new(1, 2),
+        ]);
+        assert!(CPDecimal::new(1, 127) > CPDecimal::new(i64::MAX, 100));
+        assert!(CPDecimal::new(-1, 127) < CPDecimal::new(i64::MIN, 100));
+
+        let set: HashSet<_> = [CPDecimal::new(10, -1), CPDecimal::new(1, 0), CPDecimal::new(100, -2)].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_decimal_rescale() {
+        use super::RoundingMode::*;
+        let cases = [
+            ("2.5", [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven], ["2", "3", "2", "3", "3", "2", "2"]),
+            ("-2.5", [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven], ["-2", "-3", "-3", "-2", "-3", "-2", "-2"]),
+            ("3.5", [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven], ["3", "4", "3", "4", "4", "3", "4"]),
+            ("-1.49", [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven], ["-1", "-2", "-2", "-1", "-1", "-1", "-1"]),
+        ];
+        for (value, modes, expected) in cases {
+            let value: CPDecimal = value.parse().unwrap();
+            for (mode, expected) in modes.into_iter().zip(expected) {
+                assert_eq!(value.rescale(0, mode).unwrap().to_string(), expected, "{value} {mode:?}");
+            }
+        }
+        assert_eq!(CPDecimal::new(12345, -3).rescale(-1, HalfEven).unwrap().to_string(), "12.3");
+        assert_eq!(CPDecimal::new(5, 0).rescale(-3, Down).unwrap().to_string(), "5.000");
+        assert_eq!(CPDecimal::new(5, -100).rescale(100, Up).unwrap(), CPDecimal::new(1, 100));
+        assert_eq!(CPDecimal::new(5, -100).rescale(100, HalfUp).unwrap(), CPDecimal::new(0, 0));
+        assert!(CPDecimal::new(5, 0).rescale(-19, Down).is_none());
+    }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        let a: CPDecimal = "1.25".parse().unwrap();
+        let b: CPDecimal = "-0.5".parse().unwrap();
+        assert_eq!(a.checked_add(&b).unwrap().to_string(), "0.75");
+        assert_eq!(a.checked_sub(&b).unwrap().to_string(), "1.75");
+        assert_eq!(a.checked_mul(&b).unwrap().to_string(), "-0.625");
+        assert_eq!(a.checked_add(&CPDecimal::new(0, 100)).unwrap(), a);
+        assert!(CPDecimal::new(i64::MAX, 0).checked_add(&CPDecimal::new(1, 0)).is_none());
+        assert!(CPDecimal::new(1, 100).checked_add(&CPDecimal::new(1, -100)).is_none());
+        assert!(CPDecimal::new(1, 100).checked_mul(&CPDecimal::new(1, 100)).is_none());
+    }
+
+    #[test]
+    fn test_decimal_exponent_range() {
+        // exponent 200 doesn't fit the i8 field
+        let err = from_slice::<CPDecimal>(&[CP_DECIMAL, 1, 0x80, 200]).unwrap_err();
+        assert!(matches!(err.kind(), Error::DecimalExponentOutOfRange(200)));
+        let err = cpon::from_str::<CPDecimal>(&format!("0.{:0>200}", 1)).unwrap_err();
+        assert!(err.to_string().contains("Decimal exponent -200 does not fit in i8"));
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn test_rust_decimal() {
+        use rust_decimal::Decimal;
+        use crate::ser::to_vec;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Price {
+            #[serde(with = "crate::cpdecimal::rust_decimal")]
+            amount: Decimal,
+        }
+
+        let amount: Decimal = "-12.50".parse().unwrap();
+        assert_eq!(CPDecimal::try_from(amount).unwrap(), CPDecimal::new(-1250, -2));
+        assert_eq!(Decimal::try_from(CPDecimal::new(-1250, -2)).unwrap().to_string(), "-12.50");
+        assert_eq!(Decimal::try_from(CPDecimal::new(15, 3)).unwrap().to_string(), "15000");
+        assert_eq!(Decimal::try_from(CPDecimal::new(1000, -31)).unwrap().to_string(), "0.0000000000000000000000000001");
+        assert!(matches!(Decimal::try_from(CPDecimal::new(1, 40)), Err(Error::DecimalOutOfRange(_))));
+        assert!(matches!(Decimal::try_from(CPDecimal::new(1, -29)), Err(Error::DecimalOutOfRange(_))));
+        // too wide for i64 until the trailing zeros move into the exponent
+        assert_eq!(CPDecimal::try_from(Decimal::from_i128_with_scale(7 * 10i128.pow(25), 5)).unwrap(), CPDecimal::new(7, 20));
+        assert!(matches!(CPDecimal::try_from(Decimal::MAX), Err(Error::DecimalOutOfRange(_))));
+
+        let price = Price { amount };
+        let serialized = to_vec(&price).unwrap();
+        assert_eq!(serialized, to_vec(&PriceCP { amount: CPDecimal::new(-1250, -2) }).unwrap());
+        assert_eq!(from_slice::<Price>(&serialized).unwrap(), price);
+        assert_eq!(cpon::to_string(&price).unwrap(), "{\"amount\":-12.50}");
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn test_bigdecimal() {
+        use bigdecimal::BigDecimal;
+        use crate::ser::to_vec;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Price {
+            #[serde(with = "crate::cpdecimal::bigdecimal")]
+            amount: BigDecimal,
+        }
+
+        let amount: BigDecimal = "-12.50".parse().unwrap();
+        assert_eq!(CPDecimal::try_from(&amount).unwrap(), CPDecimal::new(-1250, -2));
+        assert_eq!(BigDecimal::from(CPDecimal::new(-1250, -2)), amount);
+        assert_eq!(BigDecimal::from(CPDecimal::new(15, 3)), BigDecimal::from(15000));
+        let big: BigDecimal = "1e130".parse().unwrap();
+        assert_eq!(CPDecimal::try_from(&big).unwrap(), CPDecimal::new(1000, 127));
+        let wide: BigDecimal = "1234567890123456789000000000000".parse().unwrap();
+        assert_eq!(CPDecimal::try_from(wide).unwrap(), CPDecimal::new(1234567890123456789, 12));
+        let long: BigDecimal = "1.23456789012345678901".parse().unwrap();
+        assert!(matches!(CPDecimal::try_from(long), Err(Error::DecimalOutOfRange(_))));
+
+        let price = Price { amount };
+        let serialized = to_vec(&price).unwrap();
+        assert_eq!(serialized, to_vec(&PriceCP { amount: CPDecimal::new(-1250, -2) }).unwrap());
+        assert_eq!(from_slice::<Price>(&serialized).unwrap(), price);
+    }
+
+    #[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+    #[derive(Serialize)]
+    struct PriceCP {
+        amount: CPDecimal,
+    }
+
+    #[test]
+    fn test_decimal_from_f64() {
+        assert_eq!(CPDecimal::from_f64(0.1).unwrap(), CPDecimal::new(1, -1));
+        assert_eq!(CPDecimal::from_f64(-1234.5678).unwrap().to_string(), "-1234.5678");
+        assert_eq!(CPDecimal::from_f64(0.0).unwrap(), CPDecimal::new(0, 0));
+        assert_eq!(CPDecimal::from_f64(1e130).unwrap(), CPDecimal::new(1000, 127));
+        assert!(matches!(CPDecimal::from_f64(1e300), Err(Error::DecimalOutOfRange(_))));
+        assert!(matches!(CPDecimal::from_f64(f64::NAN), Err(Error::NonFiniteFloat(_))));
+        assert!(matches!(CPDecimal::from_f64(f64::NEG_INFINITY), Err(Error::NonFiniteFloat(_))));
+
+        assert_eq!(CPDecimal::from_f64_with_scale(21.456, 2).unwrap().to_string(), "21.46");
+        // 2.675 is slightly below that in binary, the shortest form rounds as written
+        assert_eq!(CPDecimal::from_f64_with_scale(2.675, 2).unwrap().to_string(), "2.68");
+        assert_eq!(CPDecimal::from_f64_with_scale(-0.5, 0).unwrap().to_string(), "-1");
+        assert_eq!(CPDecimal::from_f64_with_scale(3.0, 3).unwrap().to_string(), "3.000");
+        let zero = CPDecimal::from_f64_with_scale(1e-200, 2).unwrap();
+        assert_eq!((zero.mantissa(), zero.exponent()), (0, -2));
+        assert_eq!(CPDecimal::from_f64_with_scale(1234.0, -2).unwrap(), CPDecimal::new(12, 2));
+        assert!(matches!(CPDecimal::from_f64_with_scale(1e20, 2), Err(Error::DecimalOutOfRange(_))));
+        assert!(matches!(CPDecimal::from_f64_with_scale(1.0, -128), Err(Error::DecimalExponentOutOfRange(128))));
+        assert!(matches!(CPDecimal::from_f64_with_scale(f64::INFINITY, 2), Err(Error::NonFiniteFloat(_))));
+    }
 }
@@ -0,0 +1,198 @@
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::marker::PhantomData;
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+/// Integer keyed map, always written as a ChainPack `IMap` and read only from one.
+///
+/// Unlike [`CPIStruct`](crate::cpistruct::CPIStruct) the keys are dynamic,
+/// no `#[serde(rename = "1")]` fields are needed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CPIMap<V>(BTreeMap<i64, V>);
+
+pub(crate) const CP_IMAP_NEWTYPE_STRUCT: &str = "CPIMap";
+
+impl<V> CPIMap<V> {
+    pub fn new() -> Self {
+        CPIMap(BTreeMap::new())
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn get(&self, key: i64) -> Option<&V> {
+        self.0.get(&key)
+    }
+    pub fn get_mut(&mut self, key: i64) -> Option<&mut V> {
+        self.0.get_mut(&key)
+    }
+    pub fn contains_key(&self, key: i64) -> bool {
+        self.0.contains_key(&key)
+    }
+    pub fn insert(&mut self, key: i64, value: V) -> Option<V> {
+        self.0.insert(key, value)
+    }
+    pub fn remove(&mut self, key: i64) -> Option<V> {
+        self.0.remove(&key)
+    }
+    pub fn entry(&mut self, key: i64) -> btree_map::Entry<'_, i64, V> {
+        self.0.entry(key)
+    }
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+    pub fn iter(&self) -> btree_map::Iter<'_, i64, V> {
+        self.0.iter()
+    }
+    pub fn iter_mut(&mut self) -> btree_map::IterMut<'_, i64, V> {
+        self.0.iter_mut()
+    }
+    pub fn keys(&self) -> btree_map::Keys<'_, i64, V> {
+        self.0.keys()
+    }
+    pub fn values(&self) -> btree_map::Values<'_, i64, V> {
+        self.0.values()
+    }
+    pub fn into_inner(self) -> BTreeMap<i64, V> {
+        self.0
+    }
+}
+
+impl<V> Default for CPIMap<V> {
+    fn default() -> Self {
+        CPIMap::new()
+    }
+}
+
+impl<V> From<BTreeMap<i64, V>> for CPIMap<V> {
+    fn from(map: BTreeMap<i64, V>) -> Self {
+        CPIMap(map)
+    }
+}
+
+impl<V> From<CPIMap<V>> for BTreeMap<i64, V> {
+    fn from(map: CPIMap<V>) -> Self {
+        map.0
+    }
+}
+
+impl<V> std::ops::Index<i64> for CPIMap<V> {
+    type Output = V;
+
+    fn index(&self, key: i64) -> &V {
+        &self.0[&key]
+    }
+}
+
+impl<V> FromIterator<(i64, V)> for CPIMap<V> {
+    fn from_iter<I: IntoIterator<Item = (i64, V)>>(iter: I) -> Self {
+        CPIMap(iter.into_iter().collect())
+    }
+}
+
+impl<V> Extend<(i64, V)> for CPIMap<V> {
+    fn extend<I: IntoIterator<Item = (i64, V)>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl<V> IntoIterator for CPIMap<V> {
+    type Item = (i64, V);
+    type IntoIter = btree_map::IntoIter<i64, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a CPIMap<V> {
+    type Item = (&'a i64, &'a V);
+    type IntoIter = btree_map::Iter<'a, i64, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a mut CPIMap<V> {
+    type Item = (&'a i64, &'a mut V);
+    type IntoIter = btree_map::IterMut<'a, i64, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+impl<V: Serialize> Serialize for CPIMap<V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(CP_IMAP_NEWTYPE_STRUCT, &self.0)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for CPIMap<V> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(CP_IMAP_NEWTYPE_STRUCT, IMapVisitor(PhantomData))
+    }
+}
+
+struct IMapVisitor<V>(PhantomData<V>);
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for IMapVisitor<V> {
+    type Value = CPIMap<V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an IMap")
+    }
+
+    /// Formats without a dedicated IMap type deliver a plain map.
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(CPIMap(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{cpimap::CPIMap, de::from_slice, error::Error, ser::to_vec, types::{CP_IMAP, CP_INT, CP_MAP, CP_TERM}};
+
+    #[test]
+    fn test_imap() {
+        let map: CPIMap<u32> = [(1, 10), (-3, 30)].into_iter().collect();
+        let serialized = to_vec(&map).unwrap();
+        assert_eq!(serialized, [CP_IMAP, CP_INT, 0x43, 0x1e, 0x41, 0x0a, CP_TERM]);
+        assert_eq!(from_slice::<CPIMap<u32>>(&serialized).unwrap(), map);
+        assert_eq!(map[1], 10);
+        assert_eq!(map.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [-3, 1]);
+
+        let empty = CPIMap::<u32>::new();
+        assert_eq!(to_vec(&empty).unwrap(), [CP_IMAP, CP_TERM]);
+        assert_eq!(from_slice::<CPIMap<u32>>(&[CP_IMAP, CP_TERM]).unwrap(), empty);
+
+        let err = from_slice::<CPIMap<u32>>(&[CP_MAP, CP_TERM]).unwrap_err();
+        assert!(matches!(err.kind(), Error::InvalidType { .. }));
+        assert!(from_slice::<BTreeMap<i64, u32>>(&serialized).is_ok());
+    }
+}
@@ -50,10 +50,7 @@ where
         K: de::DeserializeSeed<'de>,
     {
         match self.0.next_key::<i64>()? {
-            Some(int_key) => {
-                let key_str = int_key.to_string();
-                seed.deserialize(key_str.into_deserializer()).map(Some)
-            }
+            Some(int_key) => seed.deserialize(IntKeyDeserializer(int_key, std::marker::PhantomData)).map(Some),
             None => Ok(None),
         }
     }
@@ -66,6 +63,48 @@ where
     }
 }
 
+/// Hands an IMap key to struct field identifiers as its decimal string and
+/// to everything else as an integer.
+struct IntKeyDeserializer<E>(i64, std::marker::PhantomData<E>);
+
+impl<'de, E: de::Error> Deserializer<'de> for IntKeyDeserializer<E> {
+    type Error = E;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.into_deserializer().deserialize_any(visitor)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum ignored_any
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde::{Deserialize, Serialize};
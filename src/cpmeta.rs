@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/// Value `T` prefixed with meta data `M`.
+///
+/// `M` is written as `CP_META_MAP`, struct fields renamed to integers
+/// (`#[serde(rename = "1")]`) become integer keys, other fields and map keys
+/// are written as they are. When `M` serializes as `None`, no meta map is written.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CPMeta<M, T>(pub M, pub T);
+
+pub(crate) const CP_META_TUPLE_STRUCT: &str = "CPMeta";
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+    use crate::{cpmeta::CPMeta, de::from_slice, ser::to_vec, types::{CP_LIST, CP_META_MAP, CP_NULL, CP_STRING, CP_TERM}};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct RpcMeta {
+        #[serde(rename = "1")]
+        meta_type_id: i32,
+        #[serde(rename = "8")]
+        request_id: Option<i64>,
+        #[serde(rename = "9")]
+        shv_path: String,
+        method: String,
+    }
+
+    #[test]
+    fn test_meta_struct() {
+        let value = CPMeta(
+            RpcMeta { meta_type_id: 1, request_id: Some(3), shv_path: "a".into(), method: "ls".into() },
+            "hello".to_string(),
+        );
+        let serialized = to_vec(&value).expect("serialization failed");
+        assert_eq!(serialized, vec![
+            CP_META_MAP, 0x41, 0x41, 0x48, 0x43, 0x49, CP_STRING, 1, b'a',
+            CP_STRING, 6, b'm', b'e', b't', b'h', b'o', b'd', CP_STRING, 2, b'l', b's', CP_TERM,
+            CP_STRING, 5, b'h', b'e', b'l', b'l', b'o',
+        ]);
+        let deserialized: CPMeta<RpcMeta, String> = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, value);
+    }
+
+    #[test]
+    fn test_meta_map() {
+        let meta = BTreeMap::from([(1, "foo".to_string()), (2, "bar".to_string())]);
+        let value = CPMeta(meta, vec![1, 2]);
+        let serialized = to_vec(&value).expect("serialization failed");
+        assert_eq!(serialized, vec![
+            CP_META_MAP, 0x41, CP_STRING, 3, b'f', b'o', b'o', 0x42, CP_STRING, 3, b'b', b'a', b'r', CP_TERM,
+            CP_LIST, 0x41, 0x42, CP_TERM,
+        ]);
+        let deserialized: CPMeta<BTreeMap<i32, String>, Vec<i32>> = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, value);
+
+        // values with meta can be read without it
+        let deserialized: Vec<i32> = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_meta_missing() {
+        let value: CPMeta<Option<BTreeMap<i32, i32>>, ()> = CPMeta(None, ());
+        let serialized = to_vec(&value).expect("serialization failed");
+        assert_eq!(serialized, vec![CP_NULL]);
+        let deserialized: CPMeta<Option<BTreeMap<i32, i32>>, ()> = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, value);
+        let deserialized: CPMeta<BTreeMap<i32, i32>, ()> = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, CPMeta(BTreeMap::new(), ()));
+    }
+}
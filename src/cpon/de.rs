@@ -0,0 +1,1047 @@
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+
+use super::days_from_civil;
+use crate::cpdatetime::{encode_raw, CP_DATETIME_NEWTYPE_STRUCT};
+use crate::cpdecimal::{fit_mantissa, DecimalDeserializer, CP_DECIMAL_NEWTYPE_STRUCT};
+use crate::cpimap::CP_IMAP_NEWTYPE_STRUCT;
+use crate::cpistruct::CP_ISTRUCT_NEWTYPE_STRUCT;
+use crate::cpmeta::CP_META_TUPLE_STRUCT;
+use crate::de::DEFAULT_MAX_DEPTH;
+use crate::error::{Error, Result};
+use crate::rpcvalue::CP_RPCVALUE_NEWTYPE_STRUCT;
+
+/// Deserializes a value from Cpon text, the whole input has to be consumed.
+pub fn from_str<'de, T: de::Deserialize<'de>>(s: &'de str) -> Result<T> {
+    let mut deserializer = Deserializer::from_str(s);
+    let value = T::deserialize(&mut deserializer).map_err(|e| deserializer.fix_position(e))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+pub struct Deserializer<'de> {
+    input: &'de str,
+    pos: usize,
+    max_depth: usize,
+    depth: usize,
+}
+
+enum Number {
+    Int(i128),
+    UInt(u128),
+    Double(f64),
+    Decimal(i64, i64),
+}
+
+impl<'de> Deserializer<'de> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        Deserializer { input, pos: 0, max_depth: DEFAULT_MAX_DEPTH, depth: 0 }
+    }
+
+    /// Rejects lists, maps and meta data nested deeper than `depth` levels
+    /// with [`Error::DepthLimitExceeded`], defaults to 128.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Checks that only whitespace and comments follow the deserialized value.
+    pub fn end(&mut self) -> Result<()> {
+        self.skip_whitespace()?;
+        if self.pos < self.input.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(())
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let consumed = &self.input[..self.pos];
+        let line = consumed.matches('\n').count() + 1;
+        let column = consumed.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        Error::Syntax { msg: msg.to_string(), line, column }
+    }
+
+    /// Attaches the current position to errors raised by visitors.
+    fn fix_position(&self, e: Error) -> Error {
+        match e {
+            Error::Syntax { .. } | Error::DepthLimitExceeded(_) => e,
+            e => self.error(&e.to_string()),
+        }
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.max_depth {
+            return Err(Error::DepthLimitExceeded(self.max_depth));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn rest(&self) -> &'de str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let b = self.peek().ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn expect(&mut self, b: u8) -> Result<()> {
+        match self.peek() {
+            Some(c) if c == b => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.error(&format!("expected '{}'", b as char))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str) -> Result<()> {
+        if self.rest().starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{literal}'")))
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<()> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let len = comment.find("*/").ok_or_else(|| self.error("unterminated comment"))?;
+                self.pos += len + 4;
+            } else if self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skips whitespace and an optional comma separating container items.
+    fn skip_separator(&mut self) -> Result<()> {
+        self.skip_whitespace()?;
+        if self.peek() == Some(b',') {
+            self.pos += 1;
+            self.skip_whitespace()?;
+        }
+        Ok(())
+    }
+
+    fn peek_value(&mut self) -> Result<u8> {
+        self.skip_whitespace()?;
+        self.peek().ok_or_else(|| self.error("unexpected end of input"))
+    }
+
+    fn parse_string(&mut self) -> Result<std::borrow::Cow<'de, str>> {
+        self.expect(b'"')?;
+        let rest = self.rest();
+        let len = rest.find(['"', '\\']).ok_or_else(|| self.error("unterminated string"))?;
+        if rest.as_bytes()[len] == b'"' {
+            self.pos += len + 1;
+            return Ok(std::borrow::Cow::Borrowed(&rest[..len]));
+        }
+        let mut s = String::from(&rest[..len]);
+        self.pos += len;
+        loop {
+            let rest = self.rest();
+            let c = rest.chars().next().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(std::borrow::Cow::Owned(s)),
+                '\\' => {
+                    let c = match self.next()? {
+                        b'0' => '\0',
+                        b'b' => '\x08',
+                        b'f' => '\x0c',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let hex = self.rest().get(..4).ok_or_else(|| self.error("invalid unicode escape"))?;
+                            let code = u32::from_str_radix(hex, 16).ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?;
+                            self.pos += 4;
+                            code
+                        }
+                        b @ (b'"' | b'\\' | b'/') => b as char,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    s.push(c);
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Parses the `b"..."` form, non-printable bytes are written as `\hh`.
+    fn parse_blob(&mut self) -> Result<Vec<u8>> {
+        self.expect_literal("b\"")?;
+        let mut blob = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => return Ok(blob),
+                b'\\' => {
+                    let hex = self.rest().get(..2).and_then(|h| u8::from_str_radix(h, 16).ok());
+                    if let Some(b) = hex {
+                        self.pos += 2;
+                        blob.push(b);
+                        continue;
+                    }
+                    let b = match self.next()? {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'0' => 0,
+                        b @ (b'"' | b'\\') => b,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    blob.push(b);
+                }
+                b => blob.push(b),
+            }
+        }
+    }
+
+    /// Parses the `x"..."` form, a blob written as hex digits.
+    fn parse_hex_blob(&mut self) -> Result<Vec<u8>> {
+        self.expect_literal("x\"")?;
+        let rest = self.rest();
+        let len = rest.find('"').ok_or_else(|| self.error("unterminated blob"))?;
+        let hex = &rest[..len];
+        if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+            return Err(self.error("invalid hex blob"));
+        }
+        let blob = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| self.error("invalid hex digit"))?;
+        self.pos += len + 1;
+        Ok(blob)
+    }
+
+    fn parse_digits(&mut self) -> &'de str {
+        let rest = self.rest();
+        let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn parse_number(&mut self) -> Result<Number> {
+        let start = self.pos;
+        let negative = match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                true
+            }
+            Some(b'+') => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        };
+        if self.rest().starts_with("inf") {
+            self.pos += 3;
+            return Ok(Number::Double(if negative { f64::NEG_INFINITY } else { f64::INFINITY }));
+        }
+        if self.rest().starts_with("0x") {
+            self.pos += 2;
+            let rest = self.rest();
+            let len = rest.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(rest.len());
+            self.pos += len;
+            let value = u128::from_str_radix(&rest[..len], 16).map_err(|_| self.error("invalid hex number"))?;
+            return self.finish_integer(negative, value);
+        }
+        let int_part = self.parse_digits();
+        let mut frac_part = None;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            frac_part = Some(self.parse_digits());
+        }
+        if int_part.is_empty() && frac_part.is_none_or(str::is_empty) {
+            return Err(self.error("invalid number"));
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'-' | b'+')) {
+                self.pos += 1;
+            }
+            if self.parse_digits().is_empty() {
+                return Err(self.error("invalid number exponent"));
+            }
+            let text = &self.input[start..self.pos];
+            return text.parse().map(Number::Double).map_err(|_| self.error("invalid number"));
+        }
+        if let Some(frac_part) = frac_part {
+            let digits = format!("{}{int_part}{frac_part}", if negative { "-" } else { "" });
+            // decimals with a positive exponent are written with all their zeros
+            let (mantissa, exponent) = fit_mantissa(&digits, -(frac_part.len() as i64))
+                .ok_or_else(|| self.error("decimal mantissa out of range"))?;
+            if i8::try_from(exponent).is_err() {
+                return Err(self.fix_position(Error::DecimalExponentOutOfRange(exponent)));
+            }
+            return Ok(Number::Decimal(mantissa, exponent));
+        }
+        let value = int_part.parse().map_err(|_| self.error("integer out of range"))?;
+        self.finish_integer(negative, value)
+    }
+
+    fn finish_integer(&mut self, negative: bool, value: u128) -> Result<Number> {
+        if self.peek() == Some(b'u') {
+            self.pos += 1;
+            if negative {
+                return Err(self.error("negative unsigned integer"));
+            }
+            return Ok(Number::UInt(value));
+        }
+        let value = if negative { 0i128.checked_sub_unsigned(value) } else { i128::try_from(value).ok() };
+        value.map(Number::Int).ok_or_else(|| self.error("integer out of range"))
+    }
+
+    /// Parses `d"YYYY-MM-DDTHH:MM:SS[.mmm][Z|±HH[[:]MM]]"` to its ChainPack raw value.
+    fn parse_datetime(&mut self) -> Result<i64> {
+        self.expect_literal("d\"")?;
+        let rest = self.rest();
+        let len = rest.find('"').ok_or_else(|| self.error("unterminated date time"))?;
+        let raw = parse_datetime_str(&rest[..len]).ok_or_else(|| self.error("invalid date time"))?;
+        self.pos += len + 1;
+        Ok(raw)
+    }
+
+    /// Returns the tag of values which [`RpcValue`](crate::rpcvalue::RpcValue)
+    /// can't tell apart from serde's data model.
+    fn peek_tag(&mut self) -> Result<Option<&'static str>> {
+        let tag = match self.peek_value()? {
+            b'd' if self.rest().starts_with("d\"") => Some(CP_DATETIME_NEWTYPE_STRUCT),
+            b'i' if self.rest().starts_with("i{") => Some(CP_ISTRUCT_NEWTYPE_STRUCT),
+            b'<' => Some(CP_META_TUPLE_STRUCT),
+            b'-' | b'+' | b'.' | b'0'..=b'9' => {
+                let start = self.pos;
+                let number = self.parse_number();
+                self.pos = start;
+                matches!(number, Ok(Number::Decimal(..))).then_some(CP_DECIMAL_NEWTYPE_STRUCT)
+            }
+            _ => None,
+        };
+        Ok(tag)
+    }
+
+    fn skip_meta(&mut self) -> Result<()> {
+        self.expect(b'<')?;
+        let mut access = Compound::new(self, b'>', true);
+        let mut access = &mut access;
+        while access.next_key::<de::IgnoredAny>()?.is_some() {
+            access.next_value::<de::IgnoredAny>()?;
+        }
+        Ok(())
+    }
+
+    fn visit_compound<V>(&mut self, close: u8, visitor: V, map: bool) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.nested(|de| {
+            let mut access = Compound::new(de, close, false);
+            let value = if map { visitor.visit_map(&mut access)? } else { visitor.visit_seq(&mut access)? };
+            access.finish()?;
+            Ok(value)
+        })
+    }
+}
+
+fn parse_datetime_str(s: &str) -> Option<i64> {
+    fn num<T: std::str::FromStr>(s: &str, range: std::ops::Range<usize>) -> Option<T> {
+        let digits = s.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    let b = s.as_bytes();
+    if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b' ') || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let year: i64 = num(s, 0..4)?;
+    let month: u32 = num(s, 5..7)?;
+    let day: u32 = num(s, 8..10)?;
+    let hour: i64 = num(s, 11..13)?;
+    let min: i64 = num(s, 14..16)?;
+    let sec: i64 = num(s, 17..19)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let mut rest = &s[19..];
+    let mut msec = 0;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let len = frac.find(|c: char| !c.is_ascii_digit()).unwrap_or(frac.len());
+        if len == 0 {
+            return None;
+        }
+        let digits = &frac[..len.min(3)];
+        msec = digits.parse::<i64>().ok()? * 10i64.pow(3 - digits.len() as u32);
+        rest = &frac[len..];
+    }
+    let offset_minutes = match rest {
+        "" | "Z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let zone = rest[1..].replace(':', "");
+            let (hours, minutes): (i32, i32) = match zone.len() {
+                2 => (num(&zone, 0..2)?, 0),
+                4 => (num(&zone, 0..2)?, num(&zone, 2..4)?),
+                _ => return None,
+            };
+            sign * (hours * 60 + minutes)
+        }
+    };
+    let local_msec = days_from_civil(year, month, day) * 86_400_000
+        + ((hour * 60 + min) * 60 + sec) * 1000
+        + msec;
+    Some(encode_raw(local_msec - offset_minutes as i64 * 60_000, offset_minutes))
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_value()? {
+            b'n' => {
+                self.expect_literal("null")?;
+                visitor.visit_unit()
+            }
+            b't' => {
+                self.expect_literal("true")?;
+                visitor.visit_bool(true)
+            }
+            b'f' => {
+                self.expect_literal("false")?;
+                visitor.visit_bool(false)
+            }
+            b'N' => {
+                self.expect_literal("NaN")?;
+                visitor.visit_f64(f64::NAN)
+            }
+            b'"' => match self.parse_string()? {
+                std::borrow::Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+                std::borrow::Cow::Owned(s) => visitor.visit_string(s),
+            },
+            b'b' => visitor.visit_byte_buf(self.parse_blob()?),
+            b'x' => visitor.visit_byte_buf(self.parse_hex_blob()?),
+            b'd' => visitor.visit_i64(self.parse_datetime()?),
+            b'[' => {
+                self.pos += 1;
+                self.visit_compound(b']', visitor, false)
+            }
+            b'{' => {
+                self.pos += 1;
+                self.visit_compound(b'}', visitor, true)
+            }
+            b'i' if self.rest().starts_with("i{") => {
+                self.pos += 2;
+                self.visit_compound(b'}', visitor, true)
+            }
+            b'<' => self.nested(|de| {
+                de.skip_meta()?;
+                de.deserialize_any(visitor)
+            }),
+            b'-' | b'+' | b'.' | b'0'..=b'9' | b'i' => match self.parse_number()? {
+                Number::Int(v) => match i64::try_from(v) {
+                    Ok(v) => visitor.visit_i64(v),
+                    Err(_) => visitor.visit_i128(v),
+                },
+                Number::UInt(v) => match u64::try_from(v) {
+                    Ok(v) => visitor.visit_u64(v),
+                    Err(_) => visitor.visit_u128(v),
+                },
+                Number::Double(v) => visitor.visit_f64(v),
+                Number::Decimal(mantissa, exponent) => {
+                    visitor.visit_seq(&mut DecimalDeserializer { mantissa, exponent, state: 0 })
+                }
+            },
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.peek_value()? == b'n' {
+            self.expect_literal("null")?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match name {
+            CP_DATETIME_NEWTYPE_STRUCT | CP_DECIMAL_NEWTYPE_STRUCT | CP_ISTRUCT_NEWTYPE_STRUCT => {
+                self.deserialize_any(visitor)
+            }
+            CP_IMAP_NEWTYPE_STRUCT => {
+                self.peek_value()?;
+                if !self.rest().starts_with("i{") {
+                    return Err(self.error("expected an IMap"));
+                }
+                self.deserialize_any(visitor)
+            }
+            CP_RPCVALUE_NEWTYPE_STRUCT => match self.peek_tag()? {
+                Some(tag) => visitor.visit_enum(TaggedValueAccess { de: self, tag }),
+                None => self.deserialize_any(visitor),
+            },
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(self, name: &'static str, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == CP_META_TUPLE_STRUCT {
+            let has_meta = self.peek_value()? == b'<';
+            return visitor.visit_seq(MetaSeqAccess { de: self, has_meta, index: 0 });
+        }
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_value()? {
+            b'"' => visitor.visit_enum(de::value::StrDeserializer::<Error>::new(&self.parse_string()?)),
+            b'{' => self.nested(|de| {
+                de.pos += 1;
+                let value = visitor.visit_enum(VariantAccess { de: &mut *de })?;
+                de.skip_separator()?;
+                de.expect(b'}')?;
+                Ok(value)
+            }),
+            _ => Err(self.error("expected enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        map struct identifier ignored_any
+    }
+}
+
+/// Items of a list, map or meta map, separated by whitespace or commas.
+struct Compound<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    close: u8,
+    meta_keys: bool,
+    first: bool,
+    done: bool,
+}
+
+impl<'a, 'de> Compound<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, close: u8, meta_keys: bool) -> Self {
+        Compound { de, close, meta_keys, first: true, done: false }
+    }
+
+    fn has_next(&mut self) -> Result<bool> {
+        if self.done {
+            return Ok(false);
+        }
+        if self.first {
+            self.de.skip_whitespace()?;
+            self.first = false;
+        } else {
+            self.de.skip_separator()?;
+        }
+        if self.de.peek() == Some(self.close) {
+            self.de.pos += 1;
+            self.done = true;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Consumes the closing bracket when the visitor stopped early.
+    fn finish(&mut self) -> Result<()> {
+        if self.has_next()? {
+            return Err(self.de.error(&format!("expected '{}'", self.close as char)));
+        }
+        Ok(())
+    }
+}
+
+impl<'de> SeqAccess<'de> for &mut Compound<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de> MapAccess<'de> for &mut Compound<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        if self.meta_keys {
+            seed.deserialize(MetaKeyDeserializer { de: &mut *self.de }).map(Some)
+        } else {
+            seed.deserialize(&mut *self.de).map(Some)
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.de.skip_whitespace()?;
+        self.de.expect(b':')?;
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+struct VariantAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for VariantAccess<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.de.skip_whitespace()?;
+        let variant = seed.deserialize(&mut *self.de)?;
+        self.de.skip_whitespace()?;
+        self.de.expect(b':')?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self.de)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self.de, len, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+}
+
+struct MetaSeqAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    has_meta: bool,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for MetaSeqAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.index += 1;
+        match self.index {
+            1 => seed.deserialize(MetaMapDeserializer { de: &mut *self.de, has_meta: self.has_meta }).map(Some),
+            2 => seed.deserialize(&mut *self.de).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+struct MetaMapDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    has_meta: bool,
+}
+
+impl<'de> de::Deserializer<'de> for MetaMapDeserializer<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.has_meta {
+            return visitor.visit_map(de::value::MapDeserializer::new(std::iter::empty::<((), ())>()));
+        }
+        self.de.nested(|de| {
+            de.expect(b'<')?;
+            let mut access = Compound::new(de, b'>', true);
+            let value = visitor.visit_map(&mut access)?;
+            access.finish()?;
+            Ok(value)
+        })
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.has_meta {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == CP_ISTRUCT_NEWTYPE_STRUCT {
+            return self.deserialize_any(visitor)
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Meta map keys are integers or strings, integer keys can be
+/// deserialized into both integers and struct field names.
+struct MetaKeyDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::Deserializer<'de> for MetaKeyDeserializer<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.de.peek_value()? {
+            b'-' | b'+' | b'0'..=b'9' => match self.de.parse_number()? {
+                Number::Int(key) => visitor.visit_string(key.to_string()),
+                Number::UInt(key) => visitor.visit_string(key.to_string()),
+                _ => Err(self.de.error("invalid meta key")),
+            },
+            _ => de::Deserializer::deserialize_any(self.de, visitor),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_identifier(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_identifier(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum ignored_any
+    }
+}
+
+/// Presents a value whose Cpon type has no serde counterpart as an enum
+/// variant named `tag`, so that a dynamic value visitor can tell it apart.
+struct TaggedValueAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    tag: &'static str,
+}
+
+impl<'de> de::EnumAccess<'de> for TaggedValueAccess<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(de::IntoDeserializer::<Error>::into_deserializer(self.tag))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for TaggedValueAccess<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"unit variant"))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"tuple variant"))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"struct variant"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    #[cfg(feature = "chrono")]
+    use chrono::DateTime;
+    use serde::{Deserialize, Serialize};
+    #[cfg(feature = "chrono")]
+    use crate::cpdatetime::CPDateTime;
+    use crate::{cpdatetime::RawDateTime, cpdecimal::CPDecimal, cpimap::CPIMap, cpistruct::CPIStruct, cpmeta::CPMeta, error::Error};
+    use crate::rpcvalue::{MetaKey, RpcValue};
+    use crate::cpon::to_string;
+    use super::from_str;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(from_str::<()>("null").unwrap(), ());
+        assert!(from_str::<bool>(" true ").unwrap());
+        assert_eq!(from_str::<i32>("-42").unwrap(), -42);
+        assert_eq!(from_str::<u32>("42u").unwrap(), 42);
+        assert_eq!(from_str::<i64>("0x1f").unwrap(), 31);
+        assert_eq!(from_str::<i64>(&to_string(&i64::MIN).unwrap()).unwrap(), i64::MIN);
+        assert_eq!(from_str::<i128>(&to_string(&i128::MIN).unwrap()).unwrap(), i128::MIN);
+        assert_eq!(from_str::<u128>(&to_string(&u128::MAX).unwrap()).unwrap(), u128::MAX);
+        assert_eq!(from_str::<f64>("1.5e0").unwrap(), 1.5);
+        assert_eq!(from_str::<f64>("-inf").unwrap(), f64::NEG_INFINITY);
+        assert_eq!(to_string(&0.1f32).unwrap(), "1e-1");
+        assert_eq!(from_str::<f32>("1e-1").unwrap(), 0.1);
+        assert!(from_str::<f64>("NaN").unwrap().is_nan());
+        assert_eq!(from_str::<String>(r#""a\"b\\c\n\0""#).unwrap(), "a\"b\\c\n\0");
+        assert_eq!(from_str::<&str>(r#""borrowed""#).unwrap(), "borrowed");
+        assert_eq!(from_str::<serde_bytes::ByteBuf>(r#"b"ab\22\00\ff""#).unwrap(), b"ab\"\x00\xff".to_vec());
+        assert_eq!(from_str::<serde_bytes::ByteBuf>(r#"x"0aff""#).unwrap(), b"\x0a\xff".to_vec());
+    }
+
+    #[test]
+    fn test_decimal_and_datetime() {
+        for (text, mantissa, exponent) in [("123.45", 12345, -2), ("100.", 100, 0), ("-0.001", -1, -3)] {
+            assert_eq!(from_str::<CPDecimal>(text).unwrap(), CPDecimal::new(mantissa, exponent));
+        }
+        for dec in [CPDecimal::new(1, 20), CPDecimal::new(-123, 120), CPDecimal::new(i64::MAX, i8::MAX), CPDecimal::new(i64::MIN, i8::MAX - 1)] {
+            let cpon = to_string(&dec).unwrap();
+            assert_eq!(from_str::<CPDecimal>(&cpon).unwrap(), dec);
+        }
+        let err = from_str::<CPDecimal>(&format!("1{}.", "0".repeat(200))).unwrap_err();
+        assert!(err.to_string().contains("Decimal exponent 182 does not fit in i8"));
+        assert!(from_str::<CPDecimal>("12345678901234567890123.").is_err());
+        // zeros stay decimals and keep their scale
+        for (dec, text) in [(CPDecimal::new(0, -2), "0.00"), (CPDecimal::new(0, 0), "0."), (CPDecimal::new(0, 3), "0.")] {
+            let cpon = to_string(&dec).unwrap();
+            assert_eq!(cpon, text);
+            let parsed = from_str::<CPDecimal>(&cpon).unwrap();
+            assert_eq!((parsed.mantissa(), parsed.exponent()), (0, dec.exponent().min(0)));
+        }
+        let value = RpcValue::Decimal(CPDecimal::new(0, -2));
+        assert!(matches!(from_str::<RpcValue>(&to_string(&value).unwrap()).unwrap(), RpcValue::Decimal(d) if d.exponent() == -2));
+        #[cfg(feature = "chrono")]
+        {
+            for text in ["2023-01-01T12:00:00.123+01:00", "1970-01-01T00:00:00Z", "2017-05-03T05:52:03-0130"] {
+                let dt: CPDateTime = DateTime::parse_from_rfc3339(&text.replace("-0130", "-01:30")).unwrap().into();
+                let cpon = to_string(&dt).unwrap();
+                assert_eq!(from_str::<CPDateTime>(&cpon).unwrap(), dt);
+            }
+            let dt: CPDateTime = DateTime::parse_from_rfc3339("2023-01-01T12:00:00Z").unwrap().into();
+            assert_eq!(from_str::<CPDateTime>(r#"d"2023-01-01T12:00:00""#).unwrap(), dt);
+        }
+    }
+
+    #[test]
+    fn test_containers_and_comments() {
+        let imap = std::collections::BTreeMap::from([(1u32, 2), (3, 4)]);
+        assert_eq!(to_string(&imap).unwrap(), "i{1:2,3:4}");
+        assert_eq!(from_str::<std::collections::BTreeMap<u32, i32>>("i{1:2,3:4}").unwrap(), imap);
+        assert_eq!(to_string(&std::collections::BTreeMap::<i32, i32>::new()).unwrap(), "{}");
+        let imap: CPIMap<i32> = [(1, 2)].into_iter().collect();
+        assert_eq!(to_string(&CPIMap::<i32>::new()).unwrap(), "i{}");
+        assert_eq!(from_str::<CPIMap<i32>>(" i{1:2}").unwrap(), imap);
+        assert!(from_str::<CPIMap<i32>>("{1:2}").is_err());
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum E {
+            Unit,
+            Newtype(i32),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct IStruct {
+            #[serde(rename = "1")]
+            foo: i32,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Test {
+            list: Vec<i32>,
+            map: BTreeMap<String, i32>,
+            enums: Vec<E>,
+            tuple: (i32, String),
+            meta: CPMeta<BTreeMap<i32, i32>, u32>,
+            istruct: CPIStruct<IStruct>,
+        }
+
+        let value = Test {
+            list: vec![1, 2, 3],
+            map: BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]),
+            enums: vec![E::Unit, E::Newtype(3)],
+            tuple: (1, "x".to_string()),
+            meta: CPMeta(BTreeMap::from([(1, 2)]), 3),
+            istruct: CPIStruct(IStruct { foo: 2 }),
+        };
+        assert_eq!(from_str::<Test>(&to_string(&value).unwrap()).unwrap(), value);
+
+        let text = r#"
+            // line comment
+            {
+                "list": [1 2, 3,], /* block
+                comment */
+                "map": {"a": 1, "b": 2},
+                "enums": ["Unit", {"Newtype": 3}],
+                "tuple": [1, "x"],
+                "meta": <1: 2>3u,
+                "istruct": i{1: 2},
+            }
+        "#;
+        assert_eq!(from_str::<Test>(text).unwrap(), value);
+    }
+
+    #[test]
+    fn test_rpcvalue() {
+        let text = r#"<1:1, "foo":"bar">[null, true, -42, 42u, 1.5e0, 123.45, d"2023-01-01T12:00:00.123+01", "hello", b"\01", {"a":1}, i{1:[], -2:null}]"#;
+        let value: RpcValue = from_str(text).unwrap();
+        let dt = RawDateTime { epoch_msec: 1672570800123, offset_minutes: 60 };
+        assert_eq!(value, RpcValue::Meta(
+            BTreeMap::from([
+                (MetaKey::Int(1), RpcValue::Int(1)),
+                (MetaKey::Str("foo".into()), RpcValue::String("bar".into())),
+            ]),
+            Box::new(RpcValue::List(vec![
+                RpcValue::Null,
+                RpcValue::Bool(true),
+                RpcValue::Int(-42),
+                RpcValue::UInt(42),
+                RpcValue::Double(1.5),
+                RpcValue::Decimal(CPDecimal::new(12345, -2)),
+                RpcValue::DateTime(dt),
+                RpcValue::String("hello".into()),
+                RpcValue::Blob(vec![1]),
+                RpcValue::Map(BTreeMap::from([("a".to_string(), RpcValue::Int(1))])),
+                RpcValue::IMap(BTreeMap::from([(1, RpcValue::List(vec![])), (-2, RpcValue::Null)])),
+            ])),
+        ));
+        assert_eq!(from_str::<RpcValue>(&to_string(&value).unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_error_position() {
+        match from_str::<Vec<i32>>("[1,\n  2,\n  x]") {
+            Err(Error::Syntax { line, column, .. }) => assert_eq!((line, column), (3, 3)),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        match from_str::<Vec<i32>>("[1, \"a\"]") {
+            Err(Error::Syntax { line, column, .. }) => assert_eq!((line, column), (1, 8)),
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(matches!(from_str::<i32>("1 2"), Err(Error::Syntax { line: 1, column: 3, .. })));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // would overflow the stack without the limit
+        for text in ["[".repeat(200_000), "<>".repeat(200_000), "{\"a\":".repeat(200_000), "<1:".repeat(200_000)] {
+            assert!(matches!(from_str::<serde::de::IgnoredAny>(&text), Err(Error::DepthLimitExceeded(128))));
+        }
+        for text in ["[".repeat(200_000), "<1:".repeat(200_000)] {
+            assert!(matches!(from_str::<crate::rpcvalue::RpcValue>(&text), Err(Error::DepthLimitExceeded(128))));
+        }
+        let text = format!("{}{}", "[".repeat(128), "]".repeat(128));
+        assert!(from_str::<serde::de::IgnoredAny>(&text).is_ok());
+
+        let mut deserializer = super::Deserializer::from_str("[[[1]]]").with_max_depth(2);
+        assert!(matches!(Deserialize::deserialize(&mut deserializer), Err::<Vec<Vec<Vec<i32>>>, _>(Error::DepthLimitExceeded(2))));
+        let mut deserializer = super::Deserializer::from_str("[[1]]").with_max_depth(2);
+        assert_eq!(<Vec<Vec<i32>> as Deserialize>::deserialize(&mut deserializer).unwrap(), [[1]]);
+    }
+}
@@ -0,0 +1,731 @@
+use std::io::Write;
+use serde::ser::{self, Serialize};
+use crate::cpdatetime::{decode_raw, CP_DATETIME_NEWTYPE_STRUCT};
+use super::civil_from_days;
+use crate::cpdecimal::{CPDecimal, CP_DECIMAL_NEWTYPE_STRUCT};
+use crate::cpimap::CP_IMAP_NEWTYPE_STRUCT;
+use crate::cpistruct::CP_ISTRUCT_NEWTYPE_STRUCT;
+use crate::cpmeta::CP_META_TUPLE_STRUCT;
+use crate::error::{Result, Error};
+use crate::ser::{MapKeySerializer, MapKind};
+
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let mut serializer = Serializer::new(writer);
+    value.serialize(&mut serializer)
+}
+
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut writer = Vec::new();
+    to_writer(&mut writer, value)?;
+    String::from_utf8(writer).map_err(|e| Error::InvalidUtf8(e.utf8_error()))
+}
+
+pub struct Serializer<W> {
+    writer: W,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Serializer { writer }
+    }
+
+    fn write_datetime(&mut self, raw: i64) -> Result<()> {
+        let (epoch_msec, offset_minutes) = decode_raw(raw)?;
+        let local_msec = epoch_msec.checked_add(offset_minutes as i64 * 60 * 1000).ok_or(Error::InvalidDateTime)?;
+        let days = local_msec.div_euclid(86_400_000);
+        let msec_of_day = local_msec.rem_euclid(86_400_000);
+        let (year, month, day) = civil_from_days(days);
+        let (hour, min, sec, msec) = (
+            msec_of_day / 3_600_000,
+            msec_of_day / 60_000 % 60,
+            msec_of_day / 1000 % 60,
+            msec_of_day % 1000,
+        );
+        write!(self.writer, "d\"{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}")?;
+        if msec != 0 {
+            write!(self.writer, ".{msec:03}")?;
+        }
+        if offset_minutes == 0 {
+            self.writer.write_all(b"Z")?;
+        } else {
+            let sign = if offset_minutes < 0 { '-' } else { '+' };
+            let offset = offset_minutes.abs();
+            write!(self.writer, "{sign}{:02}", offset / 60)?;
+            if offset % 60 != 0 {
+                write!(self.writer, "{:02}", offset % 60)?;
+            }
+        }
+        self.writer.write_all(b"\"")?;
+        Ok(())
+    }
+
+    fn write_decimal(&mut self, mantissa: i64, exponent: i64) -> Result<()> {
+        let exponent = i8::try_from(exponent).map_err(|_| Error::IntegerOverflow("i8"))?;
+        write!(self.writer, "{}", CPDecimal::new(mantissa, exponent))?;
+        if exponent >= 0 {
+            // the point tells a whole decimal apart from an integer
+            self.writer.write_all(b".")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
+    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
+        self.writer.write_all(if v { b"true" } else { b"false" })?;
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        write!(self.writer, "{v}")?;
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        write!(self.writer, "{v}")?;
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        write!(self.writer, "{v}u")?;
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        write!(self.writer, "{v}u")?;
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
+        // the exponent tells a double apart from a decimal
+        write!(self.writer, "{v:e}")?;
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
+        // the shortest f32 form reads back as the same f32
+        write!(self.writer, "{v:e}")?;
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        self.writer.write_all(b"\"")?;
+        for c in v.chars() {
+            match c {
+                '\0' => self.writer.write_all(b"\\0")?,
+                '\\' => self.writer.write_all(b"\\\\")?,
+                '"' => self.writer.write_all(b"\\\"")?,
+                '\t' => self.writer.write_all(b"\\t")?,
+                '\u{8}' => self.writer.write_all(b"\\b")?,
+                '\r' => self.writer.write_all(b"\\r")?,
+                '\n' => self.writer.write_all(b"\\n")?,
+                _ => self.writer.write_all(c.encode_utf8(&mut [0u8; 4]).as_bytes())?,
+            }
+        }
+        self.writer.write_all(b"\"")?;
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
+        self.writer.write_all(b"b\"")?;
+        for &b in v {
+            if (b.is_ascii_graphic() && b != b'"' && b != b'\\') || b == b' ' {
+                self.writer.write_all(&[b])?;
+            } else {
+                write!(self.writer, "\\{b:02x}")?;
+            }
+        }
+        self.writer.write_all(b"\"")?;
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        self.writer.write_all(b"null")?;
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == CP_DATETIME_NEWTYPE_STRUCT {
+            let mut ints = IntCollector(Vec::new());
+            value.serialize(&mut ints)?;
+            let [raw] = ints.0[..] else {
+                return Err(Error::UnsupportedType { method: "newtype_struct", serializer: "cpon::Serializer" })
+            };
+            return self.write_datetime(raw);
+        }
+        else if name == CP_DECIMAL_NEWTYPE_STRUCT {
+            let mut ints = IntCollector(Vec::new());
+            value.serialize(&mut ints)?;
+            let [mantissa, exponent] = ints.0[..] else {
+                return Err(Error::UnsupportedType { method: "newtype_struct", serializer: "cpon::Serializer" })
+            };
+            return self.write_decimal(mantissa, exponent);
+        }
+        else if name == CP_ISTRUCT_NEWTYPE_STRUCT || name == CP_IMAP_NEWTYPE_STRUCT {
+            let mut kms = KeyedMapSerializer { ser: self, open: "i{", close: "}" };
+            return value.serialize(&mut kms);
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.writer.write_all(b"{")?;
+        variant.serialize(&mut *self)?;
+        self.writer.write_all(b":")?;
+        value.serialize(&mut *self)?;
+        self.writer.write_all(b"}")?;
+        Ok(())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.writer.write_all(b"[")?;
+        Ok(Compound::new(self, "]"))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        if name == CP_META_TUPLE_STRUCT {
+            return Ok(Compound { meta: true, ..Compound::new(self, "") });
+        }
+        self.serialize_seq(None)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.writer.write_all(b"{")?;
+        variant.serialize(&mut *self)?;
+        self.writer.write_all(b":[")?;
+        Ok(Compound::new(self, "]}"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        // `{` or `i{` is decided by the first key
+        Ok(Compound { map: Some(MapKind::Pending), ..Compound::new(self, "}") })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.writer.write_all(b"{")?;
+        Ok(Compound::new(self, "}"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.writer.write_all(b"{")?;
+        variant.serialize(&mut *self)?;
+        self.writer.write_all(b":{")?;
+        Ok(Compound::new(self, "}}"))
+    }
+}
+
+pub struct Compound<'a, W> {
+    ser: &'a mut Serializer<W>,
+    first: bool,
+    close: &'static str,
+    /// struct fields named by an integer are written as integer keys
+    int_keys: bool,
+    /// first field is the meta map of a `CPMeta`
+    meta: bool,
+    /// kind of a map, whose opening bracket is written with the first key
+    map: Option<MapKind>,
+}
+
+impl<'a, W: Write> Compound<'a, W> {
+    fn new(ser: &'a mut Serializer<W>, close: &'static str) -> Self {
+        Compound { ser, first: true, close, int_keys: false, meta: false, map: None }
+    }
+
+    fn separate(&mut self) -> Result<()> {
+        if !self.first {
+            self.ser.writer.write_all(b",")?;
+        }
+        self.first = false;
+        Ok(())
+    }
+
+    fn write_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.separate()?;
+        match key.parse::<i64>() {
+            Ok(k) if self.int_keys => k.serialize(&mut *self.ser)?,
+            _ => key.serialize(&mut *self.ser)?,
+        }
+        self.ser.writer.write_all(b":")?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn close(self) -> Result<()> {
+        if self.map == Some(MapKind::Pending) {
+            self.ser.writer.write_all(b"{")?;
+        }
+        self.ser.writer.write_all(self.close.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.separate()?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeTuple for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.meta {
+            if self.first {
+                self.first = false;
+                let mut kms = KeyedMapSerializer { ser: &mut *self.ser, open: "<", close: ">" };
+                return value.serialize(&mut kms);
+            }
+            return value.serialize(&mut *self.ser);
+        }
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeTupleVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeMap for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let int_key = key.serialize(MapKeySerializer)?;
+        if let Some(kind) = &mut self.map && kind.settle(int_key.is_some())? {
+            self.ser.writer.write_all(if int_key.is_some() { b"i{" } else { b"{" })?;
+        }
+        self.separate()?;
+        match int_key {
+            Some(k) => k.serialize(&mut *self.ser),
+            None => key.serialize(&mut *self.ser),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser.writer.write_all(b":")?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+impl<W: Write> ser::SerializeStructVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.close()
+    }
+}
+
+/// Writes the map or struct of a `CPIStruct` as `i{...}` or the meta map
+/// of a `CPMeta` as `<...>`, a missing meta map is not written at all.
+struct KeyedMapSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+    open: &'static str,
+    close: &'static str,
+}
+
+impl<'s, 'a, W: Write> ser::Serializer for &'s mut KeyedMapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = Compound<'s, W>;
+    type SerializeStruct = Compound<'s, W>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize {
+        value.serialize(self)
+    }
+    fn serialize_none(self) -> Result<Self::Ok> { Ok(()) }
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { value.serialize(self) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Ok(()) }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bool", serializer: "KeyedMapSerializer" }) }
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i8", serializer: "KeyedMapSerializer" }) }
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i16", serializer: "KeyedMapSerializer" }) }
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i32", serializer: "KeyedMapSerializer" }) }
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i64", serializer: "KeyedMapSerializer" }) }
+    fn serialize_i128(self, _v: i128) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i128", serializer: "KeyedMapSerializer" }) }
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u8", serializer: "KeyedMapSerializer" }) }
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u16", serializer: "KeyedMapSerializer" }) }
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u32", serializer: "KeyedMapSerializer" }) }
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u64", serializer: "KeyedMapSerializer" }) }
+    fn serialize_u128(self, _v: u128) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u128", serializer: "KeyedMapSerializer" }) }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f32", serializer: "KeyedMapSerializer" }) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f64", serializer: "KeyedMapSerializer" }) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "char", serializer: "KeyedMapSerializer" }) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "str", serializer: "KeyedMapSerializer" }) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bytes", serializer: "KeyedMapSerializer" }) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_struct", serializer: "KeyedMapSerializer" }) }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_variant", serializer: "KeyedMapSerializer" }) }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "newtype_variant", serializer: "KeyedMapSerializer" }) }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Err(Error::UnsupportedType { method: "seq", serializer: "KeyedMapSerializer" }) }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Err(Error::UnsupportedType { method: "tuple", serializer: "KeyedMapSerializer" }) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Err(Error::UnsupportedType { method: "tuple_struct", serializer: "KeyedMapSerializer" }) }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Err(Error::UnsupportedType { method: "tuple_variant", serializer: "KeyedMapSerializer" }) }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.ser.writer.write_all(self.open.as_bytes())?;
+        Ok(Compound { int_keys: true, ..Compound::new(&mut *self.ser, self.close) })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant( self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Err(Error::UnsupportedType { method: "struct_variant", serializer: "KeyedMapSerializer" }) }
+}
+
+/// Collects the integers the `CPDateTime` and `CPDecimal` newtypes wrap.
+struct IntCollector(Vec<i64>);
+
+impl ser::Serializer for &mut IntCollector {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        self.0.push(v);
+        Ok(())
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Ok(self) }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bool", serializer: "IntCollector" }) }
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u8", serializer: "IntCollector" }) }
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u16", serializer: "IntCollector" }) }
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u32", serializer: "IntCollector" }) }
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u64", serializer: "IntCollector" }) }
+    fn serialize_u128(self, _v: u128) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u128", serializer: "IntCollector" }) }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f32", serializer: "IntCollector" }) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f64", serializer: "IntCollector" }) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "char", serializer: "IntCollector" }) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "str", serializer: "IntCollector" }) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bytes", serializer: "IntCollector" }) }
+    fn serialize_none(self) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "none", serializer: "IntCollector" }) }
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "some", serializer: "IntCollector" }) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit", serializer: "IntCollector" }) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_struct", serializer: "IntCollector" }) }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_variant", serializer: "IntCollector" }) }
+    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "newtype_struct", serializer: "IntCollector" }) }
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "newtype_variant", serializer: "IntCollector" }) }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Err(Error::UnsupportedType { method: "seq", serializer: "IntCollector" }) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Err(Error::UnsupportedType { method: "tuple_struct", serializer: "IntCollector" }) }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Err(Error::UnsupportedType { method: "tuple_variant", serializer: "IntCollector" }) }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> { Err(Error::UnsupportedType { method: "map", serializer: "IntCollector" }) }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> { Err(Error::UnsupportedType { method: "struct", serializer: "IntCollector" }) }
+    fn serialize_struct_variant( self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Err(Error::UnsupportedType { method: "struct_variant", serializer: "IntCollector" }) }
+}
+
+impl ser::SerializeTuple for &mut IntCollector {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    #[cfg(feature = "chrono")]
+    use chrono::DateTime;
+    use serde::Serialize;
+    #[cfg(feature = "chrono")]
+    use crate::cpdatetime::CPDateTime;
+    use crate::{cpdecimal::CPDecimal, cpistruct::CPIStruct, cpmeta::CPMeta};
+    use super::to_string;
+
+    #[test]
+    fn test_cpon_scalars() {
+        assert_eq!(to_string(&()).unwrap(), "null");
+        assert_eq!(to_string(&true).unwrap(), "true");
+        assert_eq!(to_string(&-42).unwrap(), "-42");
+        assert_eq!(to_string(&42u32).unwrap(), "42u");
+        assert_eq!(to_string(&1.5).unwrap(), "1.5e0");
+        assert_eq!(to_string(&-1234.0).unwrap(), "-1.234e3");
+        assert_eq!(to_string("a\"b\\c\n\0").unwrap(), r#""a\"b\\c\n\0""#);
+        assert_eq!(to_string(&serde_bytes::Bytes::new(b"ab\"\x00\xff")).unwrap(), r#"b"ab\22\00\ff""#);
+    }
+
+    #[test]
+    fn test_cpon_decimal() {
+        let test_cases = vec![
+            (CPDecimal::new(12345, -2), "123.45"),
+            (CPDecimal::new(-12345, -2), "-123.45"),
+            (CPDecimal::new(1, -3), "0.001"),
+            (CPDecimal::new(-1, -3), "-0.001"),
+            (CPDecimal::new(123, 0), "123."),
+            (CPDecimal::new(1, 2), "100."),
+            (CPDecimal::new(0, 0), "0."),
+        ];
+        for (dec, expected) in test_cases {
+            assert_eq!(to_string(&dec).unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_cpon_datetime() {
+        let test_cases = vec![
+            ("1970-01-01T00:00:00+00:00", r#"d"1970-01-01T00:00:00Z""#),
+            ("2018-02-02T00:00:00.001+00:00", r#"d"2018-02-02T00:00:00.001Z""#),
+            ("2018-02-02T01:00:00.001+01:00", r#"d"2018-02-02T01:00:00.001+01""#),
+            ("2017-05-03T15:52:03-01:30", r#"d"2017-05-03T15:52:03-0130""#),
+            ("2041-03-04T00:00:00.123-10:15", r#"d"2041-03-04T00:00:00.123-1015""#),
+            ("1900-02-28T23:59:59.999+00:00", r#"d"1900-02-28T23:59:59.999Z""#),
+        ];
+        for (dt_str, expected) in test_cases {
+            let dt = CPDateTime(DateTime::parse_from_rfc3339(dt_str).unwrap());
+            assert_eq!(to_string(&dt).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cpon_containers() {
+        #[derive(Serialize)]
+        struct TestIStruct {
+            #[serde(rename = "1")]
+            foo: i32,
+            #[serde(rename = "3")]
+            bar: String,
+        }
+
+        #[derive(Serialize)]
+        enum TestEnum {
+            Unit,
+            Newtype(i32),
+            Tuple(i32, i32),
+            Struct { a: i32 },
+        }
+
+        #[derive(Serialize)]
+        struct Test {
+            list: Vec<i32>,
+            map: BTreeMap<String, u8>,
+            istruct: CPIStruct<TestIStruct>,
+            meta: CPMeta<BTreeMap<&'static str, i32>, Vec<()>>,
+            enums: (TestEnum, TestEnum, TestEnum, TestEnum),
+        }
+
+        let value = Test {
+            list: vec![1, 2],
+            map: BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]),
+            istruct: CPIStruct(TestIStruct { foo: 1, bar: "baz".into() }),
+            meta: CPMeta(BTreeMap::from([("foo", 1)]), vec![()]),
+            enums: (TestEnum::Unit, TestEnum::Newtype(1), TestEnum::Tuple(1, 2), TestEnum::Struct { a: 1 }),
+        };
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"list":[1,2],"map":{"a":1u,"b":2u},"istruct":i{1:1,3:"baz"},"meta":<"foo":1>[null],"enums":["Unit",{"Newtype":1},{"Tuple":[1,2]},{"Struct":{"a":1}}]}"#
+        );
+        assert_eq!(to_string(&CPMeta(None::<BTreeMap<i32, i32>>, 1)).unwrap(), "1");
+        assert_eq!(to_string(&CPIStruct(BTreeMap::from([(1, 2)]))).unwrap(), "i{1:2}");
+    }
+}
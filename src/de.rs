@@ -1,116 +1,467 @@
-use std::io::{Read, BufReader};
+use std::io;
+use std::marker::PhantomData;
 
 use serde::de::{self, MapAccess, SeqAccess, Visitor};
 use crate::cpdatetime::CP_DATETIME_NEWTYPE_STRUCT;
 use crate::cpdecimal::{DecimalDeserializer, CP_DECIMAL_NEWTYPE_STRUCT};
+use crate::cpimap::CP_IMAP_NEWTYPE_STRUCT;
 use crate::cpistruct::CP_ISTRUCT_NEWTYPE_STRUCT;
+use crate::cpmeta::CP_META_TUPLE_STRUCT;
 use crate::error::{Result, Error};
+use crate::rpcvalue::CP_RPCVALUE_NEWTYPE_STRUCT;
+use crate::read::{IoRead, Read, Reference, SliceRead};
 use crate::types;
-use byteorder::{LittleEndian, ReadBytesExt};
 
+/// Deserializes exactly one value from `s`, trailing bytes are an error.
 pub fn from_slice<'de, T: de::Deserialize<'de>>(s: &'de [u8]) -> Result<T> {
-    let mut deserializer = Deserializer::from_reader(s);
+    let mut deserializer = Deserializer::from_slice(s);
     let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
     Ok(value)
 }
 
+/// Deserializes the first value of `s` and returns it with the bytes following it.
+pub fn from_slice_partial<'de, T: de::Deserialize<'de>>(s: &'de [u8]) -> Result<(T, &'de [u8])> {
+    let mut deserializer = Deserializer::from_slice(s);
+    let value = T::deserialize(&mut deserializer)?;
+    let consumed = deserializer.read.byte_offset() as usize;
+    Ok((value, &s[consumed..]))
+}
+
 pub struct Deserializer<R> {
-    reader: BufReader<R>,
-    peeked: Option<u8>,
+    read: R,
+    scratch: Vec<u8>,
+    max_string_len: u64,
+    max_blob_len: u64,
+    max_value_bytes: u64,
+    value_bytes: u64,
+    max_depth: usize,
+    depth: usize,
+    strict_f32: bool,
+    path: Vec<PathSegment>,
+    /// Text of the string keys in `path` which aren't borrowed from the input.
+    keys: Vec<u8>,
+    capture_key: bool,
+}
+
+/// Step of the path to the value being deserialized, reported in [`Error::At`].
+enum PathSegment {
+    Index(usize),
+    Key(PathKey),
+    Meta(PathKey),
+}
+
+/// Map key in the path, only formatted once an error is reported.
+#[derive(Clone, Copy)]
+enum PathKey {
+    None,
+    UInt(u128),
+    Int(i128),
+    /// String key at `offset..offset + len` of the input.
+    Input { offset: u64, len: usize },
+    /// String key at `start..start + len` of the deserializer's `keys`.
+    Copied { start: usize, len: usize },
+}
+
+/// Sets the key of the innermost map in `path`, replacing its previous key in `keys`.
+fn set_key(path: &mut [PathSegment], keys: &mut Vec<u8>, key: PathKey) {
+    if let Some(PathSegment::Key(k) | PathSegment::Meta(k)) = path.last_mut() {
+        if let PathKey::Copied { start, .. } = *k {
+            keys.truncate(start);
+        }
+        *k = key;
+    }
+}
+
+/// Sets a string key read at input `offset`, keys the input can't give back later are copied.
+fn set_str_key(path: &mut [PathSegment], keys: &mut Vec<u8>, offset: u64, key: &Reference<'_, '_, str>) {
+    let key = match key {
+        Reference::Borrowed(s) => PathKey::Input { offset, len: s.len() },
+        Reference::Copied(s) => {
+            set_key(path, keys, PathKey::None);
+            keys.extend_from_slice(s.as_bytes());
+            PathKey::Copied { start: keys.len() - s.len(), len: s.len() }
+        }
+    };
+    set_key(path, keys, key);
 }
 
-impl<R: Read> Deserializer<R> {
+impl<R: io::Read> Deserializer<IoRead<R>> {
     pub fn from_reader(reader: R) -> Self {
+        Deserializer::new(IoRead::new(reader))
+    }
+
+    /// Turns the deserializer into an iterator over the values following each other in its input.
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<T: de::DeserializeOwned>(self) -> StreamDeserializer<R, T> {
+        StreamDeserializer { de: self, failed: false, output: PhantomData }
+    }
+}
+
+/// Iterator over concatenated ChainPack values, as found in logs or socket captures.
+///
+/// Yields each value with the byte offset at which it starts. The iteration ends
+/// cleanly when the input ends between two values, input ending inside a value
+/// yields an error after which the iteration stops. That error is an
+/// [`Error::At`] giving the position, its [`kind`](Error::kind) is [`Error::Eof`].
+pub struct StreamDeserializer<R, T> {
+    de: Deserializer<IoRead<R>>,
+    failed: bool,
+    output: PhantomData<T>,
+}
+
+impl<R: io::Read, T: de::DeserializeOwned> StreamDeserializer<R, T> {
+    pub fn new(reader: R) -> Self {
+        Deserializer::from_reader(reader).into_iter()
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn byte_offset(&self) -> u64 {
+        self.de.read.byte_offset()
+    }
+}
+
+impl<R: io::Read, T: de::DeserializeOwned> Iterator for StreamDeserializer<R, T> {
+    type Item = Result<(u64, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let offset = self.byte_offset();
+        let result = match self.de.peek_u8() {
+            Err(Error::Eof) => return None,
+            Err(e) => Err(e),
+            Ok(_) => {
+                self.de.value_bytes = 0;
+                T::deserialize(&mut self.de)
+            }
+        };
+        self.failed = result.is_err();
+        Some(result.map(|value| (offset, value)))
+    }
+}
+
+impl<'a> Deserializer<SliceRead<'a>> {
+    /// Deserializes from an in-memory buffer, borrowing strings and blobs
+    /// from it where the target type allows.
+    pub fn from_slice(slice: &'a [u8]) -> Self {
+        Deserializer::new(SliceRead::new(slice))
+    }
+}
+
+impl<'de, R: Read<'de>> Deserializer<R> {
+    pub fn new(read: R) -> Self {
         Deserializer {
-            reader: BufReader::new(reader),
-            peeked: None,
+            read,
+            scratch: Vec::new(),
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            max_blob_len: DEFAULT_MAX_BLOB_LEN,
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            value_bytes: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            strict_f32: false,
+            path: Vec::new(),
+            keys: Vec::new(),
+            capture_key: false,
         }
     }
 
-    fn peek_u8(&mut self) -> Result<u8> {
-        if let Some(b) = self.peeked {
-            return Ok(b);
+    /// Rejects lists, maps and meta data nested deeper than `depth` levels
+    /// with [`Error::DepthLimitExceeded`], defaults to 128.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Rejects strings longer than `len` bytes with [`Error::LimitExceeded`],
+    /// defaults to 16 MiB.
+    pub fn with_max_string_len(mut self, len: u64) -> Self {
+        self.max_string_len = len;
+        self
+    }
+
+    /// Rejects blobs longer than `len` bytes with [`Error::LimitExceeded`],
+    /// defaults to 64 MiB.
+    pub fn with_max_blob_len(mut self, len: u64) -> Self {
+        self.max_blob_len = len;
+        self
+    }
+
+    /// Limits the total number of string and blob bytes which are read into
+    /// memory, defaults to 128 MiB. The count starts over for each value of a
+    /// [`StreamDeserializer`], a deserializer reused by hand counts all values
+    /// it reads. Blobs streamed by [`deserialize_blob_to`](Self::deserialize_blob_to)
+    /// are not counted.
+    pub fn with_max_value_bytes(mut self, len: u64) -> Self {
+        self.max_value_bytes = len;
+        self
+    }
+
+    /// Fail with [`Error::LossyF32`] when a `CP_DOUBLE` read into an `f32`
+    /// can't be represented exactly, instead of rounding it.
+    pub fn with_strict_f32(mut self, strict: bool) -> Self {
+        self.strict_f32 = strict;
+        self
+    }
+
+    fn check_len(&self, what: &'static str, len: u64, limit: u64) -> Result<()> {
+        if len > limit {
+            return Err(Error::LimitExceeded { what, limit });
+        }
+        Ok(())
+    }
+
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.max_depth {
+            return Err(Error::DepthLimitExceeded(self.max_depth));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn with_segment<T>(&mut self, segment: PathSegment, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.path.push(segment);
+        let result = f(self);
+        let result = self.locate(result);
+        if let Some(PathSegment::Key(PathKey::Copied { start, .. }) | PathSegment::Meta(PathKey::Copied { start, .. })) =
+            self.path.pop()
+        {
+            self.keys.truncate(start);
+        }
+        result
+    }
+
+    fn next_index(&mut self) {
+        if let Some(PathSegment::Index(i)) = self.path.last_mut() {
+            *i += 1;
+        }
+    }
+
+    fn path_string(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Index(i) => path.push_str(&format!("[{i}]")),
+                PathSegment::Key(key) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(&self.key_string(*key));
+                }
+                PathSegment::Meta(key) => path.push_str(&format!("<{}>", self.key_string(*key))),
+            }
         }
-        let b = self.reader.read_u8()?;
-        self.peeked = Some(b);
-        Ok(b)
+        path
+    }
+
+    fn key_string(&self, key: PathKey) -> String {
+        let bytes = match key {
+            PathKey::None => return String::new(),
+            PathKey::UInt(v) => return v.to_string(),
+            PathKey::Int(v) => return v.to_string(),
+            PathKey::Input { offset, len } => self.read.consumed(offset, len).unwrap_or(b"?"),
+            PathKey::Copied { start, len } => &self.keys[start..start + len],
+        };
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    /// Attaches the current offset and path to errors which don't have them yet.
+    fn locate<T>(&self, result: Result<T>) -> Result<T> {
+        result.map_err(|e| match e {
+            Error::At { .. } => e,
+            e => Error::At { offset: self.read.byte_offset(), path: self.path_string(), source: Box::new(e) },
+        })
+    }
+
+    fn charge_value_bytes(&mut self, len: u64) -> Result<()> {
+        self.value_bytes = self.value_bytes.saturating_add(len);
+        self.check_len("value", self.value_bytes, self.max_value_bytes)
+    }
+
+    /// Checks that the input has been consumed completely, call it after
+    /// the last value to detect trailing bytes.
+    pub fn end(&mut self) -> Result<()> {
+        match self.read.peek() {
+            Ok(_) => self.locate(Err(Error::TrailingBytes)),
+            Err(Error::Eof) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn peek_u8(&mut self) -> Result<u8> {
+        self.read.peek()
     }
 
     fn next_u8(&mut self) -> Result<u8> {
-        if let Some(b) = self.peeked.take() {
-            return Ok(b);
+        self.read.next()
+    }
+
+    /// Streams the next `CP_BLOB` or `CP_BLOB_CHAIN` value into `sink`
+    /// without collecting it in memory, returns the number of bytes written.
+    pub fn deserialize_blob_to<W: io::Write>(&mut self, mut sink: W) -> Result<u64> {
+        match self.next_u8()? {
+            types::CP_BLOB => {
+                let len = self.read_u64_raw_val()?;
+                self.copy_blob_data(len, &mut sink)?;
+                Ok(len)
+            }
+            types::CP_BLOB_CHAIN => self.read_blob_chain(&mut sink),
+            b => Err(invalid_type(b, &"a blob")),
         }
-        self.reader.read_u8()
-            .map_err(Error::from)
     }
 
-    fn read_u64_raw_val(&mut self) -> Result<u64> {
-        let b1 = self.reader.read_u8()?;
-        let v = if (b1 & MASK1) == PAT1 {
-            b1 as u64
+    fn read_blob_chain<W: io::Write>(&mut self, sink: &mut W) -> Result<u64> {
+        let mut total = 0;
+        loop {
+            let len = self.read_u64_raw_val()?;
+            if len == 0 {
+                return Ok(total);
+            }
+            self.copy_blob_data(len, sink)?;
+            total += len;
+        }
+    }
+
+    fn copy_blob_data<W: io::Write>(&mut self, mut len: u64, sink: &mut W) -> Result<()> {
+        while len > 0 {
+            let n = len.min(BLOB_COPY_CHUNK_SIZE) as usize;
+            let bytes = self.read.read_bytes(n, &mut self.scratch)?;
+            sink.write_all(&bytes)?;
+            len -= n as u64;
+        }
+        Ok(())
+    }
+
+    /// Reads a varint as its magnitude and, for `signed` encodings, its sign.
+    /// Magnitudes beyond 128 bits are reported as overflowing `target`.
+    fn read_raw_val(&mut self, signed: bool, target: &'static str) -> Result<(u128, bool)> {
+        let b1 = self.read.next()?;
+        let (len, head, sgn) = if (b1 & MASK1) == PAT1 {
+            (0, b1 & !MASK1, SGN1)
         } else if (b1 & MASK2) == PAT2 {
-            let b2 = self.reader.read_u8()?;
-            (((b1 & !MASK2) as u64) << 8) | b2 as u64
+            (1, b1 & !MASK2, SGN2)
         } else if (b1 & MASK3) == PAT3 {
-            let b2 = self.reader.read_u8()?;
-            let b3 = self.reader.read_u8()?;
-            (((b1 & !MASK3) as u64) << 16) | ((b2 as u64) << 8) | b3 as u64
+            (2, b1 & !MASK3, SGN3)
         } else if (b1 & MASK4) == PAT4 {
-            let b2 = self.reader.read_u8()?;
-            let b3 = self.reader.read_u8()?;
-            let b4 = self.reader.read_u8()?;
-            (((b1 & !MASK4) as u64) << 24) | ((b2 as u64) << 16) | ((b3 as u64) << 8) | b4 as u64
+            (3, b1 & !MASK4, SGN4)
         } else {
+            // the length nibble allows up to 19 bytes, more than 128 bits
             let len = (b1 & !MASK5) as usize + 4;
-            let mut buf = vec![0u8; len];
-            self.reader.read_exact(&mut buf)?;
-            let mut val = 0u64;
-            for b in buf {
-                val = (val << 8) | b as u64;
+            let mut buf = [0u8; 19];
+            let buf = &mut buf[..len];
+            self.read.read_exact(buf)?;
+            let is_neg = signed && buf[0] & SGN5 != 0;
+            if is_neg {
+                buf[0] &= !SGN5;
             }
-            val
+            let mut uval = 0u128;
+            for &b in buf.iter() {
+                if uval >> 120 != 0 {
+                    return Err(Error::IntegerOverflow(target));
+                }
+                uval = (uval << 8) | b as u128;
+            }
+            return Ok((uval, is_neg));
         };
-        Ok(v)
+        let is_neg = signed && head & sgn != 0;
+        let mut uval = if signed { head & !sgn } else { head } as u128;
+        for _ in 0..len {
+            uval = (uval << 8) | self.read.next()? as u128;
+        }
+        Ok((uval, is_neg))
+    }
+
+    fn read_u64_raw_val(&mut self) -> Result<u64> {
+        let (uval, _) = self.read_raw_val(false, "u64")?;
+        u64::try_from(uval).map_err(|_| Error::IntegerOverflow("u64"))
     }
 
     fn read_i64_raw_val(&mut self) -> Result<i64> {
-        let b1 = self.reader.read_u8()?;
-        let v = if (b1 & MASK1) == PAT1 {
-            let uval = (b1 & !SGN1) as u64;
-            if b1 & SGN1 != 0 { - (uval as i64) } else { uval as i64 }
-        } else if (b1 & MASK2) == PAT2 {
-            let b2 = self.reader.read_u8()?;
-            let mut uval = (b1 & !MASK2 & !SGN2) as u64;
-            uval = (uval << 8) | b2 as u64;
-            if b1 & SGN2 != 0 { - (uval as i64) } else { uval as i64 }
-        } else if (b1 & MASK3) == PAT3 {
-            let b2 = self.reader.read_u8()?;
-            let b3 = self.reader.read_u8()?;
-            let mut uval = (b1 & !MASK3 & !SGN3) as u64;
-            uval = (uval << 16) | ((b2 as u64) << 8) | b3 as u64;
-            if b1 & SGN3 != 0 { - (uval as i64) } else { uval as i64 }
-        } else if (b1 & MASK4) == PAT4 {
-            let b2 = self.reader.read_u8()?;
-            let b3 = self.reader.read_u8()?;
-            let b4 = self.reader.read_u8()?;
-            let mut uval = (b1 & !MASK4 & !SGN4) as u64;
-            uval = (uval << 24) | ((b2 as u64) << 16) | ((b3 as u64) << 8) | b4 as u64;
-            if b1 & SGN4 != 0 { - (uval as i64) } else { uval as i64 }
-        } else {
-            let len = (b1 & !MASK5) as usize + 4;
-            let mut buf = vec![0u8; len];
-            self.reader.read_exact(&mut buf)?;
-            let is_neg = if buf[0] & SGN5 != 0 { buf[0] = buf[0] & !SGN5; true } else { false };
-            let mut uval = 0u64;
-            for b in buf {
-                uval = (uval << 8) | b as u64;
-            }
-            if is_neg { - (uval as i64) } else { uval as i64 }
+        let v = self.read_raw_val(true, "i64")?;
+        signed_from_raw(v).and_then(|v| i64::try_from(v).ok()).ok_or(Error::IntegerOverflow("i64"))
+    }
+
+    /// Reads a value of any ChainPack integer type into `T`. Other types are
+    /// left unread and give `None`.
+    fn parse_int<T>(&mut self) -> Result<Option<T>>
+    where
+        T: TryFrom<u128> + TryFrom<i128>,
+    {
+        let type_byte = self.peek_u8()?;
+        if !matches!(type_byte, 0x00..=0x7F | types::CP_UINT | types::CP_INT) {
+            return Ok(None);
+        }
+        let capture_key = std::mem::take(&mut self.capture_key);
+        self.next_u8()?;
+        let target = std::any::type_name::<T>();
+        let v = match type_byte {
+            0x00..=0x3F => Integer::UInt(type_byte as u128),
+            0x40..=0x7F => Integer::Int(type_byte as i128 - 64),
+            types::CP_UINT => Integer::UInt(self.read_raw_val(false, target)?.0),
+            _ => Integer::Int(signed_from_raw(self.read_raw_val(true, target)?).ok_or(Error::IntegerOverflow(target))?),
+        };
+        if capture_key {
+            let key = match v {
+                Integer::UInt(v) => PathKey::UInt(v),
+                Integer::Int(v) => PathKey::Int(v),
+            };
+            set_key(&mut self.path, &mut self.keys, key);
+        }
+        let v = match v {
+            Integer::UInt(v) => T::try_from(v).ok(),
+            Integer::Int(v) => T::try_from(v).ok(),
         };
-        Ok(v)
+        v.ok_or(Error::IntegerOverflow(target)).map(Some)
     }
 }
 
+/// Value of a ChainPack UInt or Int, before narrowing to the requested type.
+enum Integer {
+    UInt(u128),
+    Int(i128),
+}
+
+/// Applies the sign read by `read_raw_val`, `None` when out of `i128` range.
+fn signed_from_raw((uval, is_neg): (u128, bool)) -> Option<i128> {
+    if is_neg {
+        0i128.checked_sub_unsigned(uval)
+    } else {
+        i128::try_from(uval).ok()
+    }
+}
+
+/// Error for a value of ChainPack type `type_byte` where `expected` was wanted.
+fn invalid_type(type_byte: u8, expected: &dyn de::Expected) -> Error {
+    let chainpack_type = types::type_name(type_byte);
+    let found = match chainpack_type {
+        Some(name) => format!("ChainPack {name}"),
+        None => format!("unknown type byte 0x{type_byte:02x}"),
+    };
+    Error::InvalidType { found, expected: expected.to_string(), chainpack_type }
+}
+
+/// Adds the ChainPack type to type mismatches reported by visitors,
+/// which only know the serde type they were given.
+fn with_chainpack_type(e: Error, type_byte: u8) -> Error {
+    match (e, types::type_name(type_byte)) {
+        (Error::InvalidType { found, expected, chainpack_type: None }, Some(name)) => {
+            Error::InvalidType { found: format!("{found} (ChainPack {name})"), expected, chainpack_type: Some(name) }
+        }
+        (e, _) => e,
+    }
+}
+
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 128;
+const DEFAULT_MAX_STRING_LEN: u64 = 16 << 20;
+const DEFAULT_MAX_BLOB_LEN: u64 = 64 << 20;
+const DEFAULT_MAX_VALUE_BYTES: u64 = 128 << 20;
+
+const BLOB_COPY_CHUNK_SIZE: u64 = 64 * 1024;
+
 const MASK1: u8 = 0b1000_0000;
 const PAT1: u8 = 0b0000_0000;
 const SGN1: u8 = 0b0100_0000;
@@ -126,26 +477,44 @@ const SGN4: u8 = 0b0000_1000;
 const MASK5: u8 = 0b1111_0000;
 const SGN5: u8 = 0b1000_0000;
 
-impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
-    type Error = Error;
-
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+impl<'de, R: Read<'de>> Deserializer<R> {
+    fn parse_any<V>(&mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        // map keys are remembered for the error path
+        let capture_key = std::mem::take(&mut self.capture_key);
         let type_byte = self.next_u8()?;
-        match type_byte {
-            0x00..=0x3F => visitor.visit_u64(type_byte as u64),
-            0x40..=0x7F => visitor.visit_i64(type_byte as i64 - 64),
-            types::CP_INT => {
-                let v = self.read_i64_raw_val()?;
-                visitor.visit_i64(v)
+        let result = match type_byte {
+            0x00..=0x3F | types::CP_UINT => {
+                let v = if type_byte == types::CP_UINT { self.read_raw_val(false, "u128")?.0 } else { type_byte as u128 };
+                if capture_key {
+                    set_key(&mut self.path, &mut self.keys, PathKey::UInt(v));
+                }
+                match u64::try_from(v) {
+                    Ok(v) => visitor.visit_u64(v),
+                    Err(_) => visitor.visit_u128(v),
+                }
             }
-            types::CP_UINT => {
-                let v = self.read_u64_raw_val()?;
-                visitor.visit_u64(v)
+            0x40..=0x7F | types::CP_INT => {
+                let v = if type_byte == types::CP_INT {
+                    signed_from_raw(self.read_raw_val(true, "i128")?).ok_or(Error::IntegerOverflow("i128"))?
+                } else {
+                    type_byte as i128 - 64
+                };
+                if capture_key {
+                    set_key(&mut self.path, &mut self.keys, PathKey::Int(v));
+                }
+                match i64::try_from(v) {
+                    Ok(v) => visitor.visit_i64(v),
+                    Err(_) => visitor.visit_i128(v),
+                }
+            }
+            types::CP_DOUBLE => {
+                let mut buf = [0u8; 8];
+                self.read.read_exact(&mut buf)?;
+                visitor.visit_f64(f64::from_le_bytes(buf))
             }
-            types::CP_DOUBLE => visitor.visit_f64(self.reader.read_f64::<LittleEndian>()?),
             types::CP_DATETIME => {
                 let v = self.read_i64_raw_val()?;
                 visitor.visit_i64(v)
@@ -153,34 +522,122 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
             types::CP_DECIMAL => {
                 let mantissa = self.read_i64_raw_val()?;
                 let exponent = self.read_i64_raw_val()?;
+                if i8::try_from(exponent).is_err() {
+                    return Err(Error::DecimalExponentOutOfRange(exponent));
+                }
                 let mut deserializer = DecimalDeserializer { mantissa, exponent, state: 0 };
                 visitor.visit_seq(&mut deserializer)
             }
             types::CP_BLOB => {
                 let len = self.read_u64_raw_val()?;
-                let mut buf = vec![0; len as usize];
-                self.reader.read_exact(&mut buf)?;
-                visitor.visit_byte_buf(buf)
+                self.check_len("blob", len, self.max_blob_len)?;
+                self.charge_value_bytes(len)?;
+                match self.read.read_bytes(len as usize, &mut self.scratch)? {
+                    Reference::Borrowed(b) => visitor.visit_borrowed_bytes(b),
+                    Reference::Copied(b) => visitor.visit_bytes(b),
+                }
             }
             types::CP_STRING => {
                 let len = self.read_u64_raw_val()?;
-                let mut buf = vec![0u8; len as usize];
-                self.reader.read_exact(&mut buf)?;
-                visitor.visit_string(String::from_utf8(buf)?)
+                self.check_len("string", len, self.max_string_len)?;
+                self.charge_value_bytes(len)?;
+                let offset = self.read.byte_offset();
+                let s = match self.read.read_bytes(len as usize, &mut self.scratch)? {
+                    Reference::Borrowed(b) => Reference::Borrowed(std::str::from_utf8(b)?),
+                    Reference::Copied(b) => Reference::Copied(std::str::from_utf8(b)?),
+                };
+                if capture_key {
+                    set_str_key(&mut self.path, &mut self.keys, offset, &s);
+                }
+                match s {
+                    Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+                    Reference::Copied(s) => visitor.visit_str(s),
+                }
             }
-            types::CP_LIST => visitor.visit_seq(self),
-            types::CP_MAP => visitor.visit_map(self),
-            types::CP_IMAP => {
-                visitor.visit_map(self)
+            types::CP_BLOB_CHAIN => {
+                let mut buf = Vec::new();
+                loop {
+                    let len = self.read_u64_raw_val()?;
+                    if len == 0 {
+                        break;
+                    }
+                    self.check_len("blob", (buf.len() as u64).saturating_add(len), self.max_blob_len)?;
+                    self.charge_value_bytes(len)?;
+                    self.copy_blob_data(len, &mut buf)?;
+                }
+                visitor.visit_byte_buf(buf)
             }
+            types::CP_CSTRING => {
+                let value_budget = self.max_value_bytes.saturating_sub(self.value_bytes);
+                let (max_len, what, limit) = if value_budget < self.max_string_len {
+                    (value_budget, "value", self.max_value_bytes)
+                } else {
+                    (self.max_string_len, "string", self.max_string_len)
+                };
+                let offset = self.read.byte_offset();
+                let cstring = self.read.read_cstring(&mut self.scratch, max_len).map_err(|e| match e {
+                    Error::LimitExceeded { .. } => Error::LimitExceeded { what, limit },
+                    e => e,
+                });
+                let s = match cstring? {
+                    Reference::Borrowed(b) => Reference::Borrowed(std::str::from_utf8(b)?),
+                    Reference::Copied(b) => Reference::Copied(std::str::from_utf8(b)?),
+                };
+                self.value_bytes += s.len() as u64;
+                if capture_key {
+                    set_str_key(&mut self.path, &mut self.keys, offset, &s);
+                }
+                match s {
+                    Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+                    Reference::Copied(s) => visitor.visit_str(s),
+                }
+            }
+            // type mismatches of containers are reported before their path is left
+            types::CP_LIST => self.nested(|de| {
+                de.with_segment(PathSegment::Index(0), |de| {
+                    visitor.visit_seq(de).map_err(|e| with_chainpack_type(e, type_byte))
+                })
+            }),
+            types::CP_MAP | types::CP_IMAP => self.nested(|de| {
+                de.with_segment(PathSegment::Key(PathKey::None), |de| {
+                    visitor.visit_map(de).map_err(|e| with_chainpack_type(e, type_byte))
+                })
+            }),
+            types::CP_META_MAP => self.nested(|de| {
+                // values are readable without knowing about their meta data
+                de.with_segment(PathSegment::Meta(PathKey::None), |de| {
+                    while MapAccess::next_entry::<de::IgnoredAny, de::IgnoredAny>(&mut &mut *de)?.is_some() {}
+                    Ok(())
+                })?;
+                de::Deserializer::deserialize_any(&mut *de, visitor)
+            }),
             types::CP_FALSE => visitor.visit_bool(false),
             types::CP_TRUE => visitor.visit_bool(true),
             types::CP_NULL => visitor.visit_unit(),
-            _ => Err(Error::InvalidType),
+            _ => Err(invalid_type(type_byte, &"a ChainPack value")),
+        };
+        result.map_err(|e| with_chainpack_type(e, type_byte))
+    }
+
+    fn parse_f32<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.peek_u8()? != types::CP_DOUBLE {
+            return de::Deserializer::deserialize_any(&mut *self, visitor);
+        }
+        self.next_u8()?;
+        let mut buf = [0u8; 8];
+        self.read.read_exact(&mut buf)?;
+        let v = f64::from_le_bytes(buf);
+        let narrowed = v as f32;
+        if self.strict_f32 && narrowed as f64 != v && !v.is_nan() {
+            return Err(Error::LossyF32(v));
         }
+        visitor.visit_f32(narrowed)
     }
 
-    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    fn parse_option<V>(&mut self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
@@ -188,51 +645,205 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
             self.next_u8()?;
             visitor.visit_none()
         } else {
-            visitor.visit_some(self)
+            visitor.visit_some(&mut *self)
         }
     }
 
-    fn deserialize_newtype_struct<V>(
-        self,
+    fn parse_newtype_struct<V>(
+        &mut self,
         name: &'static str,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if name == CP_DATETIME_NEWTYPE_STRUCT {
-            return self.deserialize_any(visitor)
+        if name == CP_DATETIME_NEWTYPE_STRUCT
+            || name == CP_DECIMAL_NEWTYPE_STRUCT
+            || name == CP_ISTRUCT_NEWTYPE_STRUCT {
+            return de::Deserializer::deserialize_any(&mut *self, visitor)
         }
-        else if name == CP_DECIMAL_NEWTYPE_STRUCT {
-            return self.deserialize_any(visitor)
+        else if name == CP_IMAP_NEWTYPE_STRUCT {
+            return match self.peek_u8()? {
+                types::CP_IMAP => de::Deserializer::deserialize_any(&mut *self, visitor),
+                b => Err(invalid_type(b, &visitor)),
+            }
         }
-        else if name == CP_ISTRUCT_NEWTYPE_STRUCT {
-            return self.deserialize_any(visitor)
+        else if name == CP_RPCVALUE_NEWTYPE_STRUCT {
+            let tag = match self.peek_u8()? {
+                types::CP_DATETIME => CP_DATETIME_NEWTYPE_STRUCT,
+                types::CP_DECIMAL => CP_DECIMAL_NEWTYPE_STRUCT,
+                types::CP_IMAP => CP_ISTRUCT_NEWTYPE_STRUCT,
+                types::CP_META_MAP => CP_META_TUPLE_STRUCT,
+                _ => return de::Deserializer::deserialize_any(&mut *self, visitor),
+            };
+            return visitor.visit_enum(TaggedValueAccess { de: self, tag })
         }
-        visitor.visit_newtype_struct(self)
+        visitor.visit_newtype_struct(&mut *self)
     }
 
-    fn deserialize_tuple<V>( self, len: usize, visitor: V, ) -> Result<V::Value>
+    fn parse_tuple<V>(&mut self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if self.peek_u8()? == types::CP_LIST {
-            self.next_u8()?;
-            let ret = visitor.visit_seq(TupleSeqAccess::new(self, len));
-            ret
-        } else {
-            Err(Error::InvalidType)
+        match self.peek_u8()? {
+            types::CP_LIST => {
+                self.next_u8()?;
+                self.nested(|de| {
+                    de.with_segment(PathSegment::Index(0), |de| visitor.visit_seq(TupleSeqAccess::new(de, len)))
+                })
+            }
+            b => Err(invalid_type(b, &"a tuple")),
+        }
+    }
+
+    fn parse_tuple_struct<V>(
+        &mut self,
+        name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == CP_META_TUPLE_STRUCT {
+            let has_meta = self.peek_u8()? == types::CP_META_MAP;
+            if has_meta {
+                self.next_u8()?;
+            }
+            return self.nested(|de| visitor.visit_seq(MetaSeqAccess { de, has_meta, index: 0 }))
+        }
+        de::Deserializer::deserialize_any(&mut *self, visitor)
+    }
+
+    fn parse_enum<V>(
+        &mut self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.peek_u8()? {
+            types::CP_STRING | types::CP_CSTRING => visitor.visit_enum(UnitVariantAccess::new(self)),
+            types::CP_MAP => {
+                self.next_u8()?;
+                let value = self.nested(|de| visitor.visit_enum(VariantAccess::new(de)))?;
+                match self.next_u8()? {
+                    types::CP_TERM => Ok(value),
+                    b => Err(invalid_type(b, &"the end of an enum map")),
+                }
+            }
+            b => Err(invalid_type(b, &"an enum")),
         }
     }
+}
+
+/// Integers are range checked here rather than in the visitor so that a value
+/// which doesn't fit fails with [`Error::IntegerOverflow`].
+macro_rules! deserialize_int {
+    ($($method:ident => $ty:ty, $visit:ident;)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                let result = match self.parse_int::<$ty>() {
+                    Ok(Some(v)) => visitor.$visit(v),
+                    Ok(None) => return de::Deserializer::deserialize_any(self, visitor),
+                    Err(err) => Err(err),
+                };
+                self.locate(result)
+            }
+        )*
+    };
+}
+
+impl<'de, R: Read<'de>> de::Deserializer<'de> for &mut Deserializer<R> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_any(visitor);
+        self.locate(result)
+    }
+
+    deserialize_int! {
+        deserialize_i8 => i8, visit_i8;
+        deserialize_i16 => i16, visit_i16;
+        deserialize_i32 => i32, visit_i32;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_i128 => i128, visit_i128;
+        deserialize_u8 => u8, visit_u8;
+        deserialize_u16 => u16, visit_u16;
+        deserialize_u32 => u32, visit_u32;
+        deserialize_u64 => u64, visit_u64;
+        deserialize_u128 => u128, visit_u128;
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_f32(visitor);
+        self.locate(result)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_option(visitor);
+        self.locate(result)
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_newtype_struct(name, visitor);
+        self.locate(result)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_tuple(len, visitor);
+        self.locate(result)
+    }
+
+    fn deserialize_tuple_struct<V>(self, name: &'static str, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_tuple_struct(name, len, visitor);
+        self.locate(result)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.parse_enum(name, variants, visitor);
+        self.locate(result)
+    }
 
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bool f64 char str string
         bytes byte_buf unit unit_struct seq
-        tuple_struct map struct enum identifier ignored_any
+        map struct identifier ignored_any
     }
 }
 
-impl<'de, 'a, R: Read> SeqAccess<'de> for &'a mut Deserializer<R> {
+impl<'de, R: Read<'de>> SeqAccess<'de> for &mut Deserializer<R> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -242,12 +853,14 @@ impl<'de, 'a, R: Read> SeqAccess<'de> for &'a mut Deserializer<R> {
         if self.peek_u8()? == types::CP_TERM {
             self.next_u8()?;
             return Ok(None);
-    }
-        seed.deserialize(&mut **self).map(Some)
+        }
+        let value = seed.deserialize(&mut **self)?;
+        self.next_index();
+        Ok(Some(value))
     }
 }
 
-impl<'de, 'a, R: Read> MapAccess<'de> for &'a mut Deserializer<R> {
+impl<'de, R: Read<'de>> MapAccess<'de> for &mut Deserializer<R> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -258,7 +871,10 @@ impl<'de, 'a, R: Read> MapAccess<'de> for &'a mut Deserializer<R> {
                 self.next_u8()?;
                 return Ok(None);
             }
-            seed.deserialize(&mut **self).map(Some)
+            self.capture_key = true;
+            let key = seed.deserialize(&mut **self);
+            self.capture_key = false;
+            key.map(Some)
         }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
@@ -269,18 +885,18 @@ impl<'de, 'a, R: Read> MapAccess<'de> for &'a mut Deserializer<R> {
     }
 }
 
-struct TupleSeqAccess<'a, R: Read> {
+struct TupleSeqAccess<'a, R> {
     de: &'a mut Deserializer<R>,
     remaining: usize,
 }
 
-impl<'a, R: Read> TupleSeqAccess<'a, R> {
+impl<'a, R> TupleSeqAccess<'a, R> {
     fn new(de: &'a mut Deserializer<R>, len: usize) -> Self {
         TupleSeqAccess { de, remaining: len }
     }
 }
 
-impl<'de, 'a, R: Read> SeqAccess<'de> for TupleSeqAccess<'a, R> {
+impl<'de, 'a, R: Read<'de>> SeqAccess<'de> for TupleSeqAccess<'a, R> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -289,13 +905,320 @@ impl<'de, 'a, R: Read> SeqAccess<'de> for TupleSeqAccess<'a, R> {
     {
         self.remaining -= 1;
         let value = seed.deserialize(&mut *self.de)?;
+        self.de.next_index();
         if self.remaining == 0 {
-            if self.de.peek_u8()? == types::CP_TERM {
-                self.de.next_u8()?;
-            } else {
-                return Err(Error::InvalidType)
+            match self.de.peek_u8()? {
+                types::CP_TERM => {
+                    self.de.next_u8()?;
+                }
+                b => return Err(invalid_type(b, &"the end of a tuple")),
             }
         }
         Ok(Some(value))
     }
-}
\ No newline at end of file
+}
+
+struct UnitVariantAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, R> UnitVariantAccess<'a, R> {
+    fn new(de: &'a mut Deserializer<R>) -> Self {
+        UnitVariantAccess { de }
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::EnumAccess<'de> for UnitVariantAccess<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::VariantAccess<'de> for UnitVariantAccess<'a, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, _seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant"))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"tuple variant"))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"struct variant"))
+    }
+}
+
+struct VariantAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, R> VariantAccess<'a, R> {
+    fn new(de: &'a mut Deserializer<R>) -> Self {
+        VariantAccess { de }
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::EnumAccess<'de> for VariantAccess<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::VariantAccess<'de> for VariantAccess<'a, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        de::Deserialize::deserialize(self.de)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self.de, len, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+}
+
+struct MetaSeqAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    has_meta: bool,
+    index: usize,
+}
+
+impl<'de, 'a, R: Read<'de>> SeqAccess<'de> for MetaSeqAccess<'a, R> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        self.index += 1;
+        match self.index {
+            1 => seed.deserialize(MetaMapDeserializer { de: &mut *self.de, has_meta: self.has_meta }).map(Some),
+            2 => seed.deserialize(&mut *self.de).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Deserializer of the meta map content following `CP_META_MAP`,
+/// an absent meta map reads as `None` or as an empty map.
+struct MetaMapDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+    has_meta: bool,
+}
+
+impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for MetaMapDeserializer<'a, R> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let done = !self.has_meta;
+        self.de.with_segment(PathSegment::Meta(PathKey::None), |de| visitor.visit_map(MetaMapAccess { de, done }))
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.has_meta {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == CP_ISTRUCT_NEWTYPE_STRUCT {
+            return self.deserialize_any(visitor)
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct MetaMapAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    done: bool,
+}
+
+impl<'de, 'a, R: Read<'de>> MapAccess<'de> for MetaMapAccess<'a, R> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        if self.de.peek_u8()? == types::CP_TERM {
+            self.de.next_u8()?;
+            self.done = true;
+            return Ok(None);
+        }
+        self.de.capture_key = true;
+        let key = seed.deserialize(MetaKeyDeserializer { de: &mut *self.de });
+        self.de.capture_key = false;
+        key.map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// Meta map keys are integers or strings, integer keys can be
+/// deserialized into both integers and struct field names.
+struct MetaKeyDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R: Read<'de>> de::Deserializer<'de> for MetaKeyDeserializer<'a, R> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.de.peek_u8()? {
+            0x00..=0x7F | types::CP_INT | types::CP_UINT => {
+                let key = <i64 as de::Deserialize>::deserialize(&mut *self.de)?;
+                visitor.visit_string(key.to_string())
+            }
+            _ => de::Deserializer::deserialize_any(self.de, visitor),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_identifier(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_identifier(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum ignored_any
+    }
+}
+
+/// Presents a value whose ChainPack type has no serde counterpart as an enum
+/// variant named `tag`, so that a dynamic value visitor can tell it apart.
+struct TaggedValueAccess<'a, R> {
+    de: &'a mut Deserializer<R>,
+    tag: &'static str,
+}
+
+impl<'de, 'a, R: Read<'de>> de::EnumAccess<'de> for TaggedValueAccess<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(de::IntoDeserializer::<Error>::into_deserializer(self.tag))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, R: Read<'de>> de::VariantAccess<'de> for TaggedValueAccess<'a, R> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"unit variant"))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"tuple variant"))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(de::Unexpected::NewtypeVariant, &"struct variant"))
+    }
+}
@@ -15,22 +15,102 @@ pub enum Error {
     IoError(#[from] io::Error),
 
     #[error("Invalid UTF-8 string")]
-    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    InvalidUtf8(#[from] std::str::Utf8Error),
 
-    #[error("Unsupported type")]
-    UnsupportedType,
+    /// A serde data model type which ChainPack can't represent, `method` is the
+    /// serializer method without its `serialize_` prefix, like `f32`.
+    #[error("Unsupported type: {method} in {serializer}")]
+    UnsupportedType { method: &'static str, serializer: &'static str },
 
-    #[error("Invalid type")]
-    InvalidType,
+    /// A value of the wrong type, `chainpack_type` is the ChainPack type of the
+    /// input when known, `found` already names it then.
+    #[error("Invalid type: found {found}, expected {expected}")]
+    InvalidType { found: String, expected: String, chainpack_type: Option<&'static str> },
+
+    #[error("Integer out of range for {0}")]
+    IntegerOverflow(&'static str),
+
+    #[error("Narrowing {0} to f32 loses precision")]
+    LossyF32(f64),
+
+    #[error("Map mixes integer and other keys")]
+    MixedMapKeys,
+
+    #[error("Trailing bytes after the value")]
+    TrailingBytes,
 
     #[error("Invalid date/time value")]
     InvalidDateTime,
+
+    #[error("Invalid decimal value")]
+    InvalidDecimal,
+
+    #[error("Decimal exponent {0} does not fit in i8")]
+    DecimalExponentOutOfRange(i64),
+
+    #[error("Decimal value out of range for {0}")]
+    DecimalOutOfRange(&'static str),
+
+    #[error("Float {0} has no decimal representation")]
+    NonFiniteFloat(f64),
+
+    #[error("{what} exceeds the limit of {limit} bytes")]
+    LimitExceeded { what: &'static str, limit: u64 },
+
+    #[error("Nesting depth exceeds the limit of {0}")]
+    DepthLimitExceeded(usize),
+
+    #[error("{msg} at line {line} column {column}")]
+    Syntax { msg: String, line: usize, column: usize },
+
+    /// Decoding error with the byte offset and the field path where it occurred.
+    #[error("{source} at offset {offset}{}", path_suffix(path))]
+    At { offset: u64, path: String, source: Box<Error> },
+}
+
+fn path_suffix(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(", path {path}")
+    }
+}
+
+impl Error {
+    /// Byte offset of the input where decoding failed.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Error::At { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Field path of the value which failed to decode, like `params.items[3].value`,
+    /// empty for the top level value.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::At { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::At { source, .. } => source.kind(),
+            e => e,
+        }
+    }
 }
 
 impl serde::de::Error for Error {
     fn custom<T: std::fmt::Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
     }
+
+    fn invalid_type(unexp: serde::de::Unexpected, exp: &dyn serde::de::Expected) -> Self {
+        Error::InvalidType { found: unexp.to_string(), expected: exp.to_string(), chainpack_type: None }
+    }
 }
 
 impl serde::ser::Error for Error {
@@ -4,8 +4,15 @@ pub mod ser;
 pub mod types;
 pub mod cpdatetime;
 pub mod cpdecimal;
-mod rawbytes;
+pub mod read;
 pub mod cpistruct;
+pub mod cpimap;
+pub mod cpmeta;
+pub mod cpon;
+pub mod rpcvalue;
+
+pub use de::{from_slice, from_slice_partial};
+pub use ser::{to_bytes, to_vec, to_writer};
 
 // pub use cpdecimal::CPDecimal as Decimal;
 // pub use cpdatetime::CPDateTime as DateTime;
@@ -53,11 +60,11 @@ pub fn hex_dump(data: &[u8]) -> String {
     output
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "chrono"))]
 mod tests {
     use chrono::DateTime;
     use serde::{Deserialize, Serialize};
-    use crate::{cpdatetime::CPDateTime, cpdecimal::CPDecimal, cpistruct::CPIStruct, de::from_slice, ser::tests::to_vec};
+    use crate::{cpdatetime::CPDateTime, cpdecimal::CPDecimal, cpistruct::CPIStruct, de::from_slice, ser::to_vec};
 
     #[test]
     fn test_struct_serde() {
@@ -0,0 +1,220 @@
+use std::io;
+use std::ops::Deref;
+
+use crate::error::{Error, Result};
+
+/// Bytes returned by [`Read::read_bytes`], either borrowed from the input
+/// for the whole `'de` lifetime or copied into the deserializer's scratch buffer.
+pub enum Reference<'b, 'c, T: ?Sized + 'static> {
+    Borrowed(&'b T),
+    Copied(&'c T),
+}
+
+impl<T: ?Sized + 'static> Deref for Reference<'_, '_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match *self {
+            Reference::Borrowed(b) => b,
+            Reference::Copied(c) => c,
+        }
+    }
+}
+
+/// Byte source of a [`Deserializer`](crate::de::Deserializer).
+///
+/// Implemented by [`IoRead`] for any [`io::Read`] and by [`SliceRead`]
+/// for in-memory input, which allows zero-copy deserialization.
+pub trait Read<'de> {
+    /// Number of bytes consumed so far, a peeked byte does not count.
+    fn byte_offset(&self) -> u64;
+    fn next(&mut self) -> Result<u8>;
+    fn peek(&mut self) -> Result<u8>;
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()>;
+    /// Reads `len` bytes, buffers grow as data arrives rather than being
+    /// allocated up front for a length which came from the input.
+    fn read_bytes<'s>(&'s mut self, len: usize, scratch: &'s mut Vec<u8>) -> Result<Reference<'de, 's, [u8]>>;
+    /// Reads zero terminated C-string data, resolving the `\0` and `\\` escapes.
+    /// Fails with [`Error::LimitExceeded`] when the data is longer than `max_len`.
+    fn read_cstring<'s>(&'s mut self, scratch: &'s mut Vec<u8>, max_len: u64) -> Result<Reference<'de, 's, [u8]>>;
+    /// Already consumed input bytes `offset..offset + len`, when they are still
+    /// available. Error paths use it to name map keys borrowed from the input.
+    fn consumed(&self, _offset: u64, _len: usize) -> Option<&[u8]> {
+        None
+    }
+}
+
+fn read_cstring_escaped<'de, R: Read<'de> + ?Sized>(read: &mut R, scratch: &mut Vec<u8>, max_len: u64) -> Result<()> {
+    loop {
+        let b = match read.next()? {
+            0 => return Ok(()),
+            b'\\' => match read.next()? {
+                b'0' => 0,
+                b => b,
+            },
+            b => b,
+        };
+        if scratch.len() as u64 >= max_len {
+            return Err(Error::LimitExceeded { what: "string", limit: max_len });
+        }
+        scratch.push(b);
+    }
+}
+
+fn map_io_error(e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        Error::Eof
+    } else {
+        Error::IoError(e)
+    }
+}
+
+/// Reads from an [`io::Read`] without reading ahead, at most one byte past the
+/// current value is consumed by a peek. Wrap unbuffered sources like files or
+/// sockets in an [`io::BufReader`] when reading ahead is not a concern.
+pub struct IoRead<R> {
+    reader: R,
+    peeked: Option<u8>,
+    offset: u64,
+}
+
+impl<R: io::Read> IoRead<R> {
+    pub fn new(reader: R) -> Self {
+        IoRead {
+            reader,
+            peeked: None,
+            offset: 0,
+        }
+    }
+}
+
+impl<R: io::Read> IoRead<R> {
+    fn read_byte(&mut self) -> Result<u8> {
+        if let Some(b) = self.peeked.take() {
+            return Ok(b);
+        }
+        let mut buf = [0u8; 1];
+        io::Read::read_exact(&mut self.reader, &mut buf).map_err(map_io_error)?;
+        Ok(buf[0])
+    }
+}
+
+impl<'de, R: io::Read> Read<'de> for IoRead<R> {
+    fn byte_offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let b = self.read_byte()?;
+        self.offset += 1;
+        Ok(b)
+    }
+
+    fn peek(&mut self) -> Result<u8> {
+        let b = self.read_byte()?;
+        self.peeked = Some(b);
+        Ok(b)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let len = buf.len();
+        let buf = match (self.peeked.take(), buf.split_first_mut()) {
+            (Some(b), Some((first, rest))) => {
+                *first = b;
+                rest
+            }
+            (peeked, _) => {
+                self.peeked = peeked;
+                buf
+            }
+        };
+        io::Read::read_exact(&mut self.reader, buf).map_err(map_io_error)?;
+        self.offset += len as u64;
+        Ok(())
+    }
+
+    fn read_bytes<'s>(&'s mut self, len: usize, scratch: &'s mut Vec<u8>) -> Result<Reference<'de, 's, [u8]>> {
+        scratch.clear();
+        if len > 0 && let Some(b) = self.peeked.take() {
+            scratch.push(b);
+        }
+        let remaining = (len - scratch.len()) as u64;
+        io::Read::read_to_end(&mut io::Read::take(&mut self.reader, remaining), scratch).map_err(map_io_error)?;
+        self.offset += scratch.len() as u64;
+        if scratch.len() < len {
+            return Err(Error::Eof);
+        }
+        Ok(Reference::Copied(&scratch[..]))
+    }
+
+    fn read_cstring<'s>(&'s mut self, scratch: &'s mut Vec<u8>, max_len: u64) -> Result<Reference<'de, 's, [u8]>> {
+        scratch.clear();
+        read_cstring_escaped(self, scratch, max_len)?;
+        Ok(Reference::Copied(&scratch[..]))
+    }
+}
+
+pub struct SliceRead<'a> {
+    slice: &'a [u8],
+    index: usize,
+}
+
+impl<'a> SliceRead<'a> {
+    pub fn new(slice: &'a [u8]) -> Self {
+        SliceRead { slice, index: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.index.checked_add(len).filter(|end| *end <= self.slice.len()).ok_or(Error::Eof)?;
+        let bytes = &self.slice[self.index..end];
+        self.index = end;
+        Ok(bytes)
+    }
+}
+
+impl<'a> Read<'a> for SliceRead<'a> {
+    fn byte_offset(&self) -> u64 {
+        self.index as u64
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        let b = self.peek()?;
+        self.index += 1;
+        Ok(b)
+    }
+
+    fn peek(&mut self) -> Result<u8> {
+        self.slice.get(self.index).copied().ok_or(Error::Eof)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    fn read_bytes<'s>(&'s mut self, len: usize, _scratch: &'s mut Vec<u8>) -> Result<Reference<'a, 's, [u8]>> {
+        self.take(len).map(Reference::Borrowed)
+    }
+
+    fn read_cstring<'s>(&'s mut self, scratch: &'s mut Vec<u8>, max_len: u64) -> Result<Reference<'a, 's, [u8]>> {
+        let rest = &self.slice[self.index..];
+        let len = rest.iter().position(|b| *b == 0 || *b == b'\\').ok_or(Error::Eof)?;
+        if len as u64 > max_len {
+            return Err(Error::LimitExceeded { what: "string", limit: max_len });
+        }
+        if rest[len] == 0 {
+            let bytes = self.take(len)?;
+            self.index += 1;
+            return Ok(Reference::Borrowed(bytes));
+        }
+        scratch.clear();
+        scratch.extend_from_slice(self.take(len)?);
+        read_cstring_escaped(self, scratch, max_len)?;
+        Ok(Reference::Copied(&scratch[..]))
+    }
+
+    fn consumed(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        let start = usize::try_from(offset).ok()?;
+        self.slice[..self.index].get(start..start.checked_add(len)?)
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::cpdatetime::{RawDateTime, CP_DATETIME_NEWTYPE_STRUCT};
+use crate::cpdecimal::{CPDecimal, CP_DECIMAL_NEWTYPE_STRUCT};
+use crate::cpistruct::{CPIStruct, CP_ISTRUCT_NEWTYPE_STRUCT};
+use crate::cpmeta::{CPMeta, CP_META_TUPLE_STRUCT};
+
+pub(crate) const CP_RPCVALUE_NEWTYPE_STRUCT: &str = "RpcValue";
+
+/// Dynamically typed ChainPack value.
+#[derive(Debug, PartialEq)]
+pub enum RpcValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Double(f64),
+    Decimal(CPDecimal),
+    DateTime(RawDateTime),
+    String(String),
+    Blob(Vec<u8>),
+    List(Vec<RpcValue>),
+    Map(BTreeMap<String, RpcValue>),
+    IMap(BTreeMap<i64, RpcValue>),
+    Meta(MetaMap, Box<RpcValue>),
+}
+
+pub type MetaMap = BTreeMap<MetaKey, RpcValue>;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetaKey {
+    Int(i64),
+    Str(String),
+}
+
+impl Serialize for MetaKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            MetaKey::Int(k) => serializer.serialize_i64(*k),
+            MetaKey::Str(k) => serializer.serialize_str(k),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MetaKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(MetaKeyVisitor)
+    }
+}
+
+struct MetaKeyVisitor;
+
+impl<'de> Visitor<'de> for MetaKeyVisitor {
+    type Value = MetaKey;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an integer or string meta key")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(MetaKey::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(MetaKey::Int)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(MetaKey::Str(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(MetaKey::Str(v))
+    }
+}
+
+impl Serialize for RpcValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            RpcValue::Null => serializer.serialize_unit(),
+            RpcValue::Bool(v) => serializer.serialize_bool(*v),
+            RpcValue::Int(v) => serializer.serialize_i64(*v),
+            RpcValue::UInt(v) => serializer.serialize_u64(*v),
+            RpcValue::Double(v) => serializer.serialize_f64(*v),
+            RpcValue::Decimal(v) => v.serialize(serializer),
+            RpcValue::DateTime(v) => v.serialize(serializer),
+            RpcValue::String(v) => serializer.serialize_str(v),
+            RpcValue::Blob(v) => serializer.serialize_bytes(v),
+            RpcValue::List(v) => {
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
+                for item in v {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            RpcValue::Map(v) => {
+                let mut map = serializer.serialize_map(Some(v.len()))?;
+                for (key, value) in v {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            RpcValue::IMap(v) => CPIStruct(v).serialize(serializer),
+            RpcValue::Meta(meta, v) => CPMeta(meta, v).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RpcValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(CP_RPCVALUE_NEWTYPE_STRUCT, RpcValueVisitor)
+    }
+}
+
+struct RpcValueVisitor;
+
+impl<'de> Visitor<'de> for RpcValueVisitor {
+    type Value = RpcValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a ChainPack value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(RpcValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(RpcValue::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RpcValue::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(RpcValue::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(RpcValue::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(RpcValue::UInt(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(RpcValue::Double(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(RpcValue::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(RpcValue::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(RpcValue::Blob(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(RpcValue::Blob(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(RpcValue::List(list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            values.insert(key, value);
+        }
+        Ok(RpcValue::Map(values))
+    }
+
+    /// Types which do not map to serde's data model directly are delivered as
+    /// an enum variant named after the newtype of their wrapper type.
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (tag, variant): (String, _) = data.variant()?;
+        match tag.as_str() {
+            CP_DATETIME_NEWTYPE_STRUCT => variant.newtype_variant().map(RpcValue::DateTime),
+            CP_DECIMAL_NEWTYPE_STRUCT => variant.newtype_variant().map(RpcValue::Decimal),
+            CP_ISTRUCT_NEWTYPE_STRUCT => variant.newtype_variant().map(RpcValue::IMap),
+            CP_META_TUPLE_STRUCT => {
+                let CPMeta(meta, value) = variant.newtype_variant::<CPMeta<MetaMap, RpcValue>>()?;
+                Ok(RpcValue::Meta(meta, Box::new(value)))
+            }
+            _ => Err(de::Error::unknown_variant(&tag, &[
+                CP_DATETIME_NEWTYPE_STRUCT,
+                CP_DECIMAL_NEWTYPE_STRUCT,
+                CP_ISTRUCT_NEWTYPE_STRUCT,
+                CP_META_TUPLE_STRUCT,
+            ])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{cpdatetime::RawDateTime, cpdecimal::CPDecimal, de::from_slice, ser::to_vec};
+    use super::{MetaKey, RpcValue};
+
+    #[test]
+    fn test_rpcvalue_serde() {
+        // 2023-01-01T12:00:00.123+01:00
+        let dt = RawDateTime { epoch_msec: 1672570800123, offset_minutes: 60 };
+        let value = RpcValue::Meta(
+            BTreeMap::from([
+                (MetaKey::Int(1), RpcValue::Int(1)),
+                (MetaKey::Str("foo".into()), RpcValue::String("bar".into())),
+            ]),
+            Box::new(RpcValue::List(vec![
+                RpcValue::Null,
+                RpcValue::Bool(true),
+                RpcValue::Int(-42),
+                RpcValue::UInt(42),
+                RpcValue::Double(1.5),
+                RpcValue::Decimal(CPDecimal::new(12345, -2)),
+                RpcValue::DateTime(dt),
+                RpcValue::String("hello".into()),
+                RpcValue::Blob(vec![1, 2, 3]),
+                RpcValue::Map(BTreeMap::from([("a".to_string(), RpcValue::Int(1))])),
+                RpcValue::IMap(BTreeMap::from([(1, RpcValue::List(vec![])), (-2, RpcValue::Null)])),
+            ])),
+        );
+        let serialized = to_vec(&value).expect("serialization failed");
+        let deserialized: RpcValue = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, value);
+        match deserialized {
+            RpcValue::Meta(_, list) => match *list {
+                RpcValue::List(items) => assert!(matches!(items[6], RpcValue::DateTime(RawDateTime { offset_minutes: 60, .. }))),
+                other => panic!("unexpected value: {other:?}"),
+            },
+            other => panic!("unexpected value: {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_rpcvalue_from_typed() {
+        use chrono::DateTime;
+        use serde::{Deserialize, Serialize};
+        use crate::{cpdatetime::CPDateTime, cpistruct::CPIStruct, cpmeta::CPMeta};
+
+        #[derive(Serialize)]
+        struct TestIStruct {
+            #[serde(rename = "1")]
+            foo: i32,
+        }
+
+        #[derive(Serialize)]
+        struct Typed {
+            timestamp: CPDateTime,
+            decimal: CPDecimal,
+            istruct: CPIStruct<TestIStruct>,
+            meta: CPMeta<BTreeMap<i32, i32>, u32>,
+        }
+
+        let dt = DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z").unwrap();
+        let typed = Typed {
+            timestamp: dt.into(),
+            decimal: CPDecimal::new(1, 2),
+            istruct: CPIStruct(TestIStruct { foo: 2 }),
+            meta: CPMeta(BTreeMap::from([(1, 2)]), 3),
+        };
+        let serialized = to_vec(&typed).expect("serialization failed");
+        let deserialized: RpcValue = from_slice(&serialized).expect("deserialization failed");
+        assert_eq!(deserialized, RpcValue::Map(BTreeMap::from([
+            ("timestamp".to_string(), RpcValue::DateTime(CPDateTime::from(dt).into())),
+            ("decimal".to_string(), RpcValue::Decimal(CPDecimal::new(1, 2))),
+            ("istruct".to_string(), RpcValue::IMap(BTreeMap::from([(1, RpcValue::Int(2))]))),
+            ("meta".to_string(), RpcValue::Meta(
+                BTreeMap::from([(MetaKey::Int(1), RpcValue::Int(2))]),
+                Box::new(RpcValue::UInt(3)),
+            )),
+        ])));
+
+        let value: RpcValue = Deserialize::deserialize(&mut crate::de::Deserializer::from_reader(&serialized[..])).expect("deserialization failed");
+        assert_eq!(value, deserialized);
+
+        match &deserialized {
+            RpcValue::Map(map) => match map["timestamp"] {
+                RpcValue::DateTime(raw) => assert_eq!(CPDateTime::try_from(raw).unwrap(), typed.timestamp),
+                ref other => panic!("unexpected value: {other:?}"),
+            },
+            other => panic!("unexpected value: {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_rpcvalue_time() {
+        use serde::Serialize;
+        use time::{OffsetDateTime, UtcOffset};
+
+        #[derive(Serialize)]
+        struct Event {
+            #[serde(with = "crate::cpdatetime::time")]
+            timestamp: OffsetDateTime,
+        }
+
+        let timestamp = OffsetDateTime::from_unix_timestamp_nanos(2246004900123 * 1_000_000).unwrap()
+            .to_offset(UtcOffset::from_whole_seconds(-615 * 60).unwrap());
+        let serialized = to_vec(&Event { timestamp }).expect("serialization failed");
+        let value: RpcValue = from_slice(&serialized).expect("deserialization failed");
+        let raw = RawDateTime { epoch_msec: 2246004900123, offset_minutes: -615 };
+        assert_eq!(value, RpcValue::Map(BTreeMap::from([("timestamp".to_string(), RpcValue::DateTime(raw))])));
+        assert_eq!(to_vec(&value).unwrap(), serialized);
+
+        let converted = match &value {
+            RpcValue::Map(map) => match map["timestamp"] {
+                RpcValue::DateTime(raw) => OffsetDateTime::try_from(raw).unwrap(),
+                ref other => panic!("unexpected value: {other:?}"),
+            },
+            other => panic!("unexpected value: {other:?}"),
+        };
+        assert_eq!(converted, timestamp);
+        assert_eq!(converted.offset(), timestamp.offset());
+        assert_eq!(RawDateTime::from(timestamp).offset_minutes, -615);
+        assert!(OffsetDateTime::try_from(RawDateTime { epoch_msec: 0, offset_minutes: 100_000 }).is_err());
+    }
+}
@@ -1,12 +1,16 @@
-use std::io::Write;
+use std::io::{self, Write};
 use serde::ser::{self, Serialize};
 use serde::ser::Error as SerdeError;
 use crate::cpdatetime::CP_DATETIME_NEWTYPE_STRUCT;
+use crate::cpimap::CP_IMAP_NEWTYPE_STRUCT;
 use crate::cpistruct::CP_ISTRUCT_NEWTYPE_STRUCT;
+use crate::cpmeta::CP_META_TUPLE_STRUCT;
 use crate::error::{Result, Error};
-use crate::rawbytes::CP_RAWBYTES_NEWTYPE_STRUCT;
+use crate::cpdecimal::CP_DECIMAL_NEWTYPE_STRUCT;
+use crate::de::DEFAULT_MAX_DEPTH;
 use crate::types;
 use byteorder::{LittleEndian, WriteBytesExt};
+use bytes::Bytes;
 
 struct RawBytesSerializer<'a, W: Write> {
     pub(crate) ser: &'a mut Serializer<W>,
@@ -17,14 +21,14 @@ impl<'s, 'a, W: Write> ser::Serializer for &'s mut RawBytesSerializer<'a, W> {
     type Error = Error;
 
     type SerializeSeq = ser::Impossible<(), Error>;
-    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTuple = Self;
     type SerializeTupleStruct = ser::Impossible<(), Error>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
-    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeMap = Self;
     type SerializeStruct = Self;
     type SerializeStructVariant = ser::Impossible<(), Error>;
 
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok> where T: Serialize {
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize {
         value.serialize(self)
     }
 
@@ -32,49 +36,105 @@ impl<'s, 'a, W: Write> ser::Serializer for &'s mut RawBytesSerializer<'a, W> {
         self.ser.writer.write_all(v).map_err(Error::from)
     }
 
-    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_i8(self, _v: i8) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_i16(self, _v: i16) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_i32(self, _v: i32) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bool", serializer: "RawBytesSerializer" }) }
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> { self.serialize_i64(v as i64) }
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        serialize_raw_i64(&mut self.ser.writer, v).map_err(Error::from)
+        serialize_raw_i64(&mut self.ser.writer, v)
     }
-    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        serialize_raw_i128(&mut self.ser.writer, v)
+    }
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u8", serializer: "RawBytesSerializer" }) }
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u16", serializer: "RawBytesSerializer" }) }
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u32", serializer: "RawBytesSerializer" }) }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
-        serialize_raw_u64(&mut self.ser.writer, v).map_err(Error::from)
-    }
-    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_none(self) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_some<T: ?Sized>(self, _value: &T) -> Result<Self::Ok> where T: Serialize { Err(Error::UnsupportedType) }
-    fn serialize_unit(self) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType) }
-
-    fn serialize_newtype_variant<T: ?Sized>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: Serialize { Err(Error::UnsupportedType) }
-    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Err(Error::UnsupportedType) }
-    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Err(Error::UnsupportedType) }
-    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Err(Error::UnsupportedType) }
-    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Err(Error::UnsupportedType) }
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {  Err(Error::UnsupportedType)  }
+        serialize_raw_u64(&mut self.ser.writer, v)
+    }
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        serialize_raw_u128(&mut self.ser.writer, v)
+    }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f32", serializer: "RawBytesSerializer" }) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f64", serializer: "RawBytesSerializer" }) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "char", serializer: "RawBytesSerializer" }) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "str", serializer: "RawBytesSerializer" }) }
+    fn serialize_none(self) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "none", serializer: "RawBytesSerializer" }) }
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "some", serializer: "RawBytesSerializer" }) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit", serializer: "RawBytesSerializer" }) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_struct", serializer: "RawBytesSerializer" }) }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_variant", serializer: "RawBytesSerializer" }) }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "newtype_variant", serializer: "RawBytesSerializer" }) }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Err(Error::UnsupportedType { method: "seq", serializer: "RawBytesSerializer" }) }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Ok(self) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Err(Error::UnsupportedType { method: "tuple_struct", serializer: "RawBytesSerializer" }) }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Err(Error::UnsupportedType { method: "tuple_variant", serializer: "RawBytesSerializer" }) }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.ser.enter()?;
+        self.ser.writer.write_u8(types::CP_IMAP)?;
+        Ok(self)
+    }
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.ser.enter()?;
         self.ser.writer.write_u8(types::CP_IMAP)?;
         Ok(self)
     }
-    fn serialize_struct_variant( self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Err(Error::UnsupportedType) }
+    fn serialize_struct_variant( self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Err(Error::UnsupportedType { method: "struct_variant", serializer: "RawBytesSerializer" }) }
+}
+
+impl<'s, 'a, W: Write> ser::SerializeTuple for &'s mut RawBytesSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(())
+    }
+}
+
+impl<'s, 'a, W: Write> ser::SerializeMap for &'s mut RawBytesSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        // unsigned keys are written as Int too
+        match key.serialize(MapKeySerializer)? {
+            Some(k) => ser::Serializer::serialize_i64(&mut *self.ser, k),
+            None => key.serialize(&mut *self.ser),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.ser.writer.write_u8(types::CP_TERM)?;
+        self.ser.leave();
+        Ok(())
+    }
 }
 
 impl<'s, 'a, W: Write> ser::SerializeStruct for &'s mut RawBytesSerializer<'a, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let k = key.parse::<i64>().map_err(|e| Error::custom(e.to_string()))?;
         k.serialize(&mut *self.ser)?;
@@ -83,50 +143,170 @@ impl<'s, 'a, W: Write> ser::SerializeStruct for &'s mut RawBytesSerializer<'a, W
 
     fn end(self) -> Result<Self::Ok> {
         self.ser.writer.write_u8(types::CP_TERM)?;
+        self.ser.leave();
+        Ok(())
+    }
+}
+
+struct MetaMapSerializer<'a, W: Write> {
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'s, 'a, W: Write> ser::Serializer for &'s mut MetaMapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize {
+        value.serialize(self)
+    }
+    fn serialize_none(self) -> Result<Self::Ok> { Ok(()) }
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { value.serialize(self) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Ok(()) }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bool", serializer: "MetaMapSerializer" }) }
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i8", serializer: "MetaMapSerializer" }) }
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i16", serializer: "MetaMapSerializer" }) }
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i32", serializer: "MetaMapSerializer" }) }
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i64", serializer: "MetaMapSerializer" }) }
+    fn serialize_i128(self, _v: i128) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "i128", serializer: "MetaMapSerializer" }) }
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u8", serializer: "MetaMapSerializer" }) }
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u16", serializer: "MetaMapSerializer" }) }
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u32", serializer: "MetaMapSerializer" }) }
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u64", serializer: "MetaMapSerializer" }) }
+    fn serialize_u128(self, _v: u128) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "u128", serializer: "MetaMapSerializer" }) }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f32", serializer: "MetaMapSerializer" }) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "f64", serializer: "MetaMapSerializer" }) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "char", serializer: "MetaMapSerializer" }) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "str", serializer: "MetaMapSerializer" }) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "bytes", serializer: "MetaMapSerializer" }) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_struct", serializer: "MetaMapSerializer" }) }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Err(Error::UnsupportedType { method: "unit_variant", serializer: "MetaMapSerializer" }) }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Err(Error::UnsupportedType { method: "newtype_variant", serializer: "MetaMapSerializer" }) }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Err(Error::UnsupportedType { method: "seq", serializer: "MetaMapSerializer" }) }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Err(Error::UnsupportedType { method: "tuple", serializer: "MetaMapSerializer" }) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Err(Error::UnsupportedType { method: "tuple_struct", serializer: "MetaMapSerializer" }) }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Err(Error::UnsupportedType { method: "tuple_variant", serializer: "MetaMapSerializer" }) }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.ser.enter()?;
+        self.ser.writer.write_u8(types::CP_META_MAP)?;
+        Ok(self)
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.ser.enter()?;
+        self.ser.writer.write_u8(types::CP_META_MAP)?;
+        Ok(self)
+    }
+    fn serialize_struct_variant( self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Err(Error::UnsupportedType { method: "struct_variant", serializer: "MetaMapSerializer" }) }
+}
+
+impl<'s, 'a, W: Write> ser::SerializeMap for &'s mut MetaMapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        key.serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.ser.writer.write_u8(types::CP_TERM)?;
+        self.ser.leave();
+        Ok(())
+    }
+}
+
+impl<'s, 'a, W: Write> ser::SerializeStruct for &'s mut MetaMapSerializer<'a, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match key.parse::<i64>() {
+            Ok(k) => k.serialize(&mut *self.ser)?,
+            Err(_) => key.serialize(&mut *self.ser)?,
+        }
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.ser.writer.write_u8(types::CP_TERM)?;
+        self.ser.leave();
         Ok(())
     }
 }
 
 pub(crate) fn serialize_raw_i64<W: Write>(writer: &mut W, v: i64) -> Result<()> {
-    let uv = if v < 0 { -v } else { v };
-    let bits = 64 - uv.leading_zeros() + 1;
+    serialize_raw_i128(writer, v as i128)
+}
+
+pub(crate) fn serialize_raw_i128<W: Write>(writer: &mut W, v: i128) -> Result<()> {
+    // sign and magnitude, so i128::MIN needs no special case
+    let is_neg = v < 0;
+    let uv = v.unsigned_abs();
+    let bits = 128 - uv.leading_zeros() + 1;
     if bits <= 7 {
         let mut b = uv as u8;
-        if v != uv { b |= 0b0100_0000 }
+        if is_neg { b |= 0b0100_0000 }
         writer.write_u8(b)?;
     } else if bits <= 14 {
         let mut b = 0b1000_0000 | (uv >> 8) as u8;
-        if v != uv { b |= 0b0010_0000 }
+        if is_neg { b |= 0b0010_0000 }
         writer.write_u8(b)?;
         writer.write_u8((uv & 0xFF) as u8)?;
     } else if bits <= 21 {
         let mut b = 0b1100_0000 | (uv >> 16) as u8;
-        if v != uv { b |= 0b0001_0000 }
+        if is_neg { b |= 0b0001_0000 }
         writer.write_u8(b)?;
         writer.write_u8(((uv >> 8) & 0xFF) as u8)?;
         writer.write_u8((uv & 0xFF) as u8)?;
     } else if bits <= 28 {
         let mut b = 0b1110_0000 | (uv >> 24) as u8;
-        if v != uv { b |= 0b0000_1000 }
+        if is_neg { b |= 0b0000_1000 }
         writer.write_u8(b)?;
         writer.write_u8(((uv >> 16) & 0xFF) as u8)?;
         writer.write_u8(((uv >> 8) & 0xFF) as u8)?;
         writer.write_u8((uv & 0xFF) as u8)?;
     } else {
-        let num_bytes = (bits as usize + 7) / 8;
+        let num_bytes = (bits as usize).div_ceil(8);
         writer.write_u8(0xF0 | ((num_bytes - 4) as u8))?;
-        let bytes = uv.to_be_bytes();
-        let mut b = bytes[8 - num_bytes];
-        if v != uv { b |= 0b1000_0000 }
+        // a 128 bit magnitude plus the sign bit takes 17 bytes
+        let mut bytes = [0u8; 17];
+        bytes[1..].copy_from_slice(&uv.to_be_bytes());
+        let mut b = bytes[17 - num_bytes];
+        if is_neg { b |= 0b1000_0000 }
         writer.write_u8(b)?;
-        let bytes = &bytes[8 - num_bytes + 1..];
+        let bytes = &bytes[17 - num_bytes + 1..];
         writer.write_all(bytes)?;
     }
     Ok(())
 }
 
 pub(crate) fn serialize_raw_u64<W: Write>(writer: &mut W, v: u64) -> Result<()> {
-    let bits = 64 - v.leading_zeros();
+    serialize_raw_u128(writer, v as u128)
+}
+
+pub(crate) fn serialize_raw_u128<W: Write>(writer: &mut W, v: u128) -> Result<()> {
+    let bits = 128 - v.leading_zeros();
     if bits <= 7 {
         writer.write_u8(v as u8)?;
     } else if bits <= 14 {
@@ -143,20 +323,96 @@ pub(crate) fn serialize_raw_u64<W: Write>(writer: &mut W, v: u64) -> Result<()>
         writer.write_u8((v & 0xFF) as u8)?;
     }
     else {
-        let num_bytes = (bits as usize + 7) / 8;
+        let num_bytes = (bits as usize).div_ceil(8);
         writer.write_u8(0xF0 | ((num_bytes - 4) as u8))?;
-        writer.write_all(&v.to_be_bytes()[8 - num_bytes..])?;
+        writer.write_all(&v.to_be_bytes()[16 - num_bytes..])?;
     }
     Ok(())
 }
 
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: Write,
+    T: ?Sized + Serialize,
+{
+    let mut serializer = Serializer::new(writer);
+    value.serialize(&mut serializer)
+}
+
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut writer = Vec::new();
+    to_writer(&mut writer, value)?;
+    Ok(writer)
+}
+
+pub fn to_bytes<T>(value: &T) -> Result<Bytes>
+where
+    T: ?Sized + Serialize,
+{
+    to_vec(value).map(Bytes::from)
+}
+
+const BLOB_CHAIN_CHUNK_SIZE: usize = 64 * 1024;
+
 pub struct Serializer<W> {
     pub(crate) writer: W,
+    cstrings: bool,
+    max_depth: usize,
+    depth: usize,
 }
 
 impl<W: Write> Serializer<W> {
     pub fn new(writer: W) -> Self {
-        Serializer { writer }
+        Serializer { writer, cstrings: false, max_depth: DEFAULT_MAX_DEPTH, depth: 0 }
+    }
+
+    /// Fails with [`Error::DepthLimitExceeded`] instead of recursing through data
+    /// nested deeper than `depth` levels, defaults to the deserializer's limit of 128.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.max_depth {
+            return Err(Error::DepthLimitExceeded(self.max_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Write strings as zero terminated `CP_CSTRING` instead of length prefixed `CP_STRING`.
+    pub fn with_cstrings(mut self, cstrings: bool) -> Self {
+        self.cstrings = cstrings;
+        self
+    }
+
+    /// Writes everything `reader` yields as a `CP_BLOB_CHAIN`, one chunk per read,
+    /// so the total length does not have to be known up front.
+    pub fn serialize_blob_chain<R: io::Read>(&mut self, mut reader: R) -> Result<u64> {
+        self.writer.write_u8(types::CP_BLOB_CHAIN)?;
+        let mut buf = vec![0u8; BLOB_CHAIN_CHUNK_SIZE];
+        let mut total = 0;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            serialize_raw_u64(&mut self.writer, n as u64)?;
+            self.writer.write_all(&buf[..n])?;
+            total += n as u64;
+        }
+        serialize_raw_u64(&mut self.writer, 0)?;
+        Ok(total)
     }
 }
 
@@ -166,9 +422,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
     type SerializeSeq = Self;
     type SerializeTuple = Self;
-    type SerializeTupleStruct = Self;
+    type SerializeTupleStruct = TupleStructSerializer<'a, W>;
     type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
+    type SerializeMap = MapSerializer<'a, W>;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
@@ -190,7 +446,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        if v >= 0 && v < 64 {
+        if (0..64).contains(&v) {
             self.writer.write_u8(0x40 + v as u8)?;
         } else {
             self.writer.write_u8(types::CP_INT)?;
@@ -199,6 +455,16 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         Ok(())
     }
 
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => {
+                self.writer.write_u8(types::CP_INT)?;
+                serialize_raw_i128(&mut self.writer, v)
+            }
+        }
+    }
+
     fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
         self.serialize_u64(v as u64)
     }
@@ -221,14 +487,25 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         Ok(())
     }
 
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        match u64::try_from(v) {
+            Ok(v) => self.serialize_u64(v),
+            Err(_) => {
+                self.writer.write_u8(types::CP_UINT)?;
+                serialize_raw_u128(&mut self.writer, v)
+            }
+        }
+    }
+
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_DOUBLE)?;
         self.writer.write_f64::<LittleEndian>(v)?;
         Ok(())
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> {
-        Err(Error::UnsupportedType)
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
+        // ChainPack has no single precision type, widening is lossless
+        self.serialize_f64(v as f64)
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
@@ -236,10 +513,22 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        if self.cstrings {
+            self.writer.write_u8(types::CP_CSTRING)?;
+            for &b in v.as_bytes() {
+                match b {
+                    0 => self.writer.write_all(b"\\0")?,
+                    b'\\' => self.writer.write_all(b"\\\\")?,
+                    _ => self.writer.write_u8(b)?,
+                }
+            }
+            self.writer.write_u8(0)?;
+            return Ok(());
+        }
         self.writer.write_u8(types::CP_STRING)?;
         let bytes = v.as_bytes();
         let len = bytes.len() as u64;
-        self.serialize_u64(len)?;
+        serialize_raw_u64(&mut self.writer, len)?;
         self.writer.write_all(bytes)?;
         Ok(())
     }
@@ -247,7 +536,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_BLOB)?;
         let len = v.len() as u64;
-        self.serialize_u64(len)?;
+        serialize_raw_u64(&mut self.writer, len)?;
         self.writer.write_all(v)?;
         Ok(())
     }
@@ -256,9 +545,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self.serialize_unit()
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
@@ -281,32 +570,32 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         self.serialize_str(variant)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         name: &'static str,
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        println!("SER newtype struct: {name}");
         if name == CP_DATETIME_NEWTYPE_STRUCT {
             self.writer.write_u8(types::CP_DATETIME)?;
             let mut rbs = RawBytesSerializer{ ser: self };
             return value.serialize(&mut rbs);
         }
-        else if name == CP_RAWBYTES_NEWTYPE_STRUCT {
+        else if name == CP_DECIMAL_NEWTYPE_STRUCT {
+            self.writer.write_u8(types::CP_DECIMAL)?;
             let mut rbs = RawBytesSerializer{ ser: self };
             return value.serialize(&mut rbs);
         }
-        else if name == CP_ISTRUCT_NEWTYPE_STRUCT {
+        else if name == CP_ISTRUCT_NEWTYPE_STRUCT || name == CP_IMAP_NEWTYPE_STRUCT {
             let mut rbs = RawBytesSerializer{ ser: self };
             return value.serialize(&mut rbs);
         }
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -314,16 +603,19 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        self.enter()?;
         self.writer.write_u8(types::CP_MAP)?;
         variant.serialize(&mut *self)?;
         value.serialize(&mut *self)?;
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.enter()?;
         self.writer.write_u8(types::CP_LIST)?;
         Ok(self)
     }
@@ -334,11 +626,15 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
     fn serialize_tuple_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        self.writer.write_u8(types::CP_LIST)?;
-        Ok(self)
+        let meta = name == CP_META_TUPLE_STRUCT;
+        self.enter()?;
+        if !meta {
+            self.writer.write_u8(types::CP_LIST)?;
+        }
+        Ok(TupleStructSerializer { ser: self, meta, index: 0 })
     }
 
     fn serialize_tuple_variant(
@@ -348,6 +644,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
+        self.enter()?;
         self.writer.write_u8(types::CP_MAP)?;
         variant.serialize(&mut *self)?;
         self.writer.write_u8(types::CP_LIST)?;
@@ -355,13 +652,15 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.writer.write_u8(types::CP_MAP)?;
-        Ok(self)
+        self.enter()?;
+        // CP_MAP or CP_IMAP is decided by the first key
+        Ok(MapSerializer { ser: self, kind: MapKind::Pending })
     }
 
-    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        println!("SER struct name: {name}");
-        self.serialize_map(Some(len))
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.enter()?;
+        self.writer.write_u8(types::CP_MAP)?;
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -371,6 +670,7 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
+        self.enter()?;
         self.writer.write_u8(types::CP_MAP)?;
         variant.serialize(&mut *self)?;
         self.writer.write_u8(types::CP_MAP)?;
@@ -378,64 +678,81 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeSeq for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTuple for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
+pub struct TupleStructSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+    meta: bool,
+    index: usize,
+}
+
+impl<W: Write> ser::SerializeTupleStruct for TupleStructSerializer<'_, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        let index = self.index;
+        self.index += 1;
+        if self.meta && index == 0 {
+            let mut mms = MetaMapSerializer { ser: self.ser };
+            return value.serialize(&mut mms);
+        }
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.writer.write_u8(types::CP_TERM)?;
+        if !self.meta {
+            self.ser.writer.write_u8(types::CP_TERM)?;
+        }
+        self.ser.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTupleVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -443,41 +760,164 @@ impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
     fn end(self) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_TERM)?;
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for &'a mut Serializer<W> {
+pub struct MapSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+    kind: MapKind,
+}
+
+impl<W: Write> ser::SerializeMap for MapSerializer<'_, W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)
+        let int_key = key.serialize(MapKeySerializer)?;
+        if self.kind.settle(int_key.is_some())? {
+            let type_byte = if int_key.is_some() { types::CP_IMAP } else { types::CP_MAP };
+            self.ser.writer.write_u8(type_byte)?;
+        }
+        match int_key {
+            Some(k) => ser::Serializer::serialize_i64(&mut *self.ser, k),
+            None => key.serialize(&mut *self.ser),
+        }
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        self.writer.write_u8(types::CP_TERM)?;
+        if self.kind == MapKind::Pending {
+            self.ser.writer.write_u8(types::CP_MAP)?;
+        }
+        self.ser.writer.write_u8(types::CP_TERM)?;
+        self.ser.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<W> {
+/// Type of a map whose header is only written once its first key is known.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum MapKind {
+    Pending,
+    Map,
+    IMap,
+}
+
+impl MapKind {
+    /// Records the kind of a key, `true` for the first one which decides the
+    /// type of the map. Keys of the other kind are an error.
+    pub(crate) fn settle(&mut self, int_key: bool) -> Result<bool> {
+        let kind = if int_key { MapKind::IMap } else { MapKind::Map };
+        match *self {
+            MapKind::Pending => {
+                *self = kind;
+                Ok(true)
+            }
+            current if current == kind => Ok(false),
+            _ => Err(Error::MixedMapKeys),
+        }
+    }
+}
+
+/// Tells integer map keys, which make a map an IMap, from all others.
+pub(crate) struct MapKeySerializer;
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = Option<i64>;
+    type Error = Error;
+
+    type SerializeSeq = OtherKey;
+    type SerializeTuple = OtherKey;
+    type SerializeTupleStruct = OtherKey;
+    type SerializeTupleVariant = OtherKey;
+    type SerializeMap = OtherKey;
+    type SerializeStruct = OtherKey;
+    type SerializeStructVariant = OtherKey;
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> { Ok(Some(v)) }
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> { i64::try_from(v).map(Some).map_err(|_| Error::IntegerOverflow("i64")) }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> { Ok(Some(v as i64)) }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> { i64::try_from(v).map(Some).map_err(|_| Error::IntegerOverflow("i64")) }
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> { i64::try_from(v).map(Some).map_err(|_| Error::IntegerOverflow("i64")) }
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { value.serialize(self) }
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize {
+        // the magic newtypes are written as their own ChainPack types, not as integers
+        match name {
+            CP_DATETIME_NEWTYPE_STRUCT | CP_DECIMAL_NEWTYPE_STRUCT | CP_IMAP_NEWTYPE_STRUCT | CP_ISTRUCT_NEWTYPE_STRUCT => Ok(None),
+            _ => value.serialize(self),
+        }
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_none(self) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<Self::Ok> { Ok(None) }
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<Self::Ok> where T: ?Sized + Serialize { Ok(None) }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> { Ok(OtherKey) }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> { Ok(OtherKey) }
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> { Ok(OtherKey) }
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant> { Ok(OtherKey) }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> { Ok(OtherKey) }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> { Ok(OtherKey) }
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant> { Ok(OtherKey) }
+}
+
+/// Compound map key, skipped as it can't be an integer.
+pub(crate) struct OtherKey;
+
+macro_rules! impl_other_key {
+    ($($trait:ident { $($method:ident $(($key:ident))?),* }),* $(,)?) => {$(
+        impl ser::$trait for OtherKey {
+            type Ok = Option<i64>;
+            type Error = Error;
+
+            $(fn $method<T>(&mut self, $($key: &'static str,)? _value: &T) -> Result<()> where T: ?Sized + Serialize { Ok(()) })*
+
+            fn end(self) -> Result<Self::Ok> { Ok(None) }
+        }
+    )*};
+}
+
+impl_other_key! {
+    SerializeSeq { serialize_element },
+    SerializeTuple { serialize_element },
+    SerializeTupleStruct { serialize_field },
+    SerializeTupleVariant { serialize_field },
+    SerializeMap { serialize_key, serialize_value },
+    SerializeStruct { serialize_field(_key) },
+    SerializeStructVariant { serialize_field(_key) },
+}
+
+impl<W: Write> ser::SerializeStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         key.serialize(&mut **self)?;
         value.serialize(&mut **self)
@@ -485,17 +925,18 @@ impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<W> {
 
     fn end(self) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeStructVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         key.serialize(&mut **self)?;
         value.serialize(&mut **self)
@@ -504,20 +945,7 @@ impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<W> {
     fn end(self) -> Result<Self::Ok> {
         self.writer.write_u8(types::CP_TERM)?;
         self.writer.write_u8(types::CP_TERM)?;
+        self.leave();
         Ok(())
     }
 }
-
-#[cfg(test)]
-pub(crate) mod tests {
-    use serde::Serialize;
-
-    use super::Serializer;
-
-    pub(crate) fn to_vec<T: Serialize>(value: &T) -> crate::error::Result<Vec<u8>> {
-        let mut writer = Vec::new();
-        let mut serializer = Serializer::new(&mut writer);
-        value.serialize(&mut serializer)?;
-        Ok(writer)
-    }
-}
@@ -15,3 +15,27 @@ pub const CP_BLOB_CHAIN: u8 = 0x8f;
 pub const CP_FALSE: u8 = 0xFD;
 pub const CP_TRUE: u8 = 0xFE;
 pub const CP_TERM: u8 = 0xFF;
+
+/// Human readable name of the ChainPack type starting with `type_byte`.
+pub fn type_name(type_byte: u8) -> Option<&'static str> {
+    let name = match type_byte {
+        0x00..=0x3F | CP_UINT => "UInt",
+        0x40..=0x7F | CP_INT => "Int",
+        CP_NULL => "Null",
+        CP_DOUBLE => "Double",
+        CP_BLOB => "Blob",
+        CP_STRING => "String",
+        CP_LIST => "List",
+        CP_MAP => "Map",
+        CP_IMAP => "IMap",
+        CP_META_MAP => "MetaMap",
+        CP_DECIMAL => "Decimal",
+        CP_DATETIME => "DateTime",
+        CP_CSTRING => "CString",
+        CP_BLOB_CHAIN => "BlobChain",
+        CP_FALSE | CP_TRUE => "Bool",
+        CP_TERM => "Term",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -1,8 +1,9 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_bytes::ByteBuf;
-use serde_chainpack::{de::Deserializer, ser::Serializer, types::{CP_BLOB, CP_DOUBLE, CP_INT, CP_LIST, CP_MAP, CP_NULL, CP_STRING, CP_TERM, CP_UINT}};
+use serde_chainpack::{de::{Deserializer, StreamDeserializer}, error::Error, from_slice, from_slice_partial, rpcvalue::RpcValue, to_bytes, to_vec, to_writer, ser::Serializer, types::{CP_BLOB, CP_BLOB_CHAIN, CP_CSTRING, CP_DOUBLE, CP_IMAP, CP_INT, CP_LIST, CP_MAP, CP_NULL, CP_STRING, CP_TERM, CP_UINT}};
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_bool() {
     let mut buffer = Vec::new();
     let mut serializer = Serializer::new(&mut buffer);
@@ -77,6 +78,22 @@ fn test_bytes() {
     assert_eq!(value, vec![1, 2, 3, 4, 5]);
 }
 
+#[test]
+fn test_long_str_and_bytes() {
+    // lengths from 64 up don't fit a tiny uint and used to get a CP_UINT prefix
+    let s = "x".repeat(64);
+    let buffer = to_vec(&s).unwrap();
+    assert_eq!(buffer[..2], [CP_STRING, 64]);
+    assert_eq!(buffer.len(), 2 + 64);
+    assert_eq!(from_slice::<String>(&buffer).unwrap(), s);
+
+    let blob = ByteBuf::from(vec![7u8; 64]);
+    let buffer = to_vec(&blob).unwrap();
+    assert_eq!(buffer[..2], [CP_BLOB, 64]);
+    assert_eq!(buffer.len(), 2 + 64);
+    assert_eq!(from_slice::<ByteBuf>(&buffer).unwrap(), blob);
+}
+
 #[test]
 fn test_option() {
     let mut buffer = Vec::new();
@@ -247,3 +264,548 @@ fn test_int_examples() {
         assert_eq!(deserialized_value, value);
     }
 }
+
+#[test]
+fn test_to_vec_to_writer_to_bytes() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestStruct {
+        a: i32,
+        b: Vec<u32>,
+    }
+
+    let test_struct = TestStruct { a: 1, b: vec![2, 3] };
+    let expected = vec![CP_MAP, CP_STRING, 1, b'a', 0x41, CP_STRING, 1, b'b', CP_LIST, 0x02, 0x03, CP_TERM, CP_TERM];
+
+    let vec = to_vec(&test_struct).unwrap();
+    assert_eq!(vec, expected);
+
+    let mut buffer = Vec::new();
+    to_writer(&mut buffer, &test_struct).unwrap();
+    assert_eq!(buffer, expected);
+
+    let bytes = to_bytes(&test_struct).unwrap();
+    assert_eq!(&bytes[..], &expected[..]);
+
+    let value: TestStruct = from_slice(&bytes).unwrap();
+    assert_eq!(value, test_struct);
+}
+
+#[test]
+fn test_borrowed() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        alias: std::borrow::Cow<'a, str>,
+        #[serde(with = "serde_bytes")]
+        data: &'a [u8],
+    }
+
+    let value = Borrowed { name: "foo", alias: "bar".into(), data: &[1, 2, 3] };
+    let buffer = to_vec(&value).unwrap();
+
+    let deserialized: Borrowed = from_slice(&buffer).unwrap();
+    assert_eq!(deserialized, value);
+    assert!(matches!(deserialized.alias, std::borrow::Cow::Borrowed(_)));
+    let name_offset = buffer.windows(3).position(|w| w == b"foo").unwrap();
+    assert!(std::ptr::eq(deserialized.name.as_ptr(), &buffer[name_offset]));
+
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    assert!(Borrowed::deserialize(&mut deserializer).is_err());
+}
+
+#[test]
+fn test_enum() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum TestEnum {
+        Unit,
+        Newtype(i32),
+        Tuple(i32, String),
+        Struct { a: i32, b: String },
+    }
+
+    let test_cases = vec![
+        (TestEnum::Unit, vec![CP_STRING, 4, b'U', b'n', b'i', b't']),
+        (TestEnum::Newtype(1), vec![CP_MAP, CP_STRING, 7, b'N', b'e', b'w', b't', b'y', b'p', b'e', 0x41, CP_TERM]),
+        (
+            TestEnum::Tuple(1, "a".to_string()),
+            vec![CP_MAP, CP_STRING, 5, b'T', b'u', b'p', b'l', b'e', CP_LIST, 0x41, CP_STRING, 1, b'a', CP_TERM, CP_TERM],
+        ),
+        (
+            TestEnum::Struct { a: 1, b: "b".to_string() },
+            vec![
+                CP_MAP, CP_STRING, 6, b'S', b't', b'r', b'u', b'c', b't',
+                CP_MAP, CP_STRING, 1, b'a', 0x41, CP_STRING, 1, b'b', CP_STRING, 1, b'b', CP_TERM, CP_TERM
+            ],
+        ),
+    ];
+
+    for (value, expected) in test_cases {
+        let buffer = to_vec(&value).unwrap();
+        assert_eq!(buffer, expected);
+
+        let deserialized: TestEnum = from_slice(&buffer).unwrap();
+        assert_eq!(deserialized, value);
+    }
+
+    let values = vec![TestEnum::Unit, TestEnum::Newtype(42), TestEnum::Struct { a: 2, b: "c".to_string() }];
+    let buffer = to_vec(&values).unwrap();
+    let deserialized: Vec<TestEnum> = from_slice(&buffer).unwrap();
+    assert_eq!(deserialized, values);
+}
+
+#[test]
+fn test_cstring() {
+    let mut buffer = Vec::new();
+    let mut serializer = Serializer::new(&mut buffer).with_cstrings(true);
+    serde::Serializer::serialize_str(&mut serializer, "a\0b\\c").unwrap();
+    assert_eq!(buffer, vec![CP_CSTRING, b'a', b'\\', b'0', b'b', b'\\', b'\\', b'c', 0]);
+
+    let value: String = from_slice(&buffer).unwrap();
+    assert_eq!(value, "a\0b\\c");
+
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    let value = String::deserialize(&mut deserializer).unwrap();
+    assert_eq!(value, "a\0b\\c");
+
+    let buffer = vec![CP_CSTRING, b'h', b'e', b'l', b'l', b'o', 0];
+    let value: &str = from_slice(&buffer).unwrap();
+    assert_eq!(value, "hello");
+
+    let mut buffer = Vec::new();
+    let mut serializer = Serializer::new(&mut buffer).with_cstrings(true);
+    let mut map = std::collections::BTreeMap::new();
+    map.insert("key".to_string(), "value".to_string());
+    map.serialize(&mut serializer).unwrap();
+    assert_eq!(buffer, vec![CP_MAP, CP_CSTRING, b'k', b'e', b'y', 0, CP_CSTRING, b'v', b'a', b'l', b'u', b'e', 0, CP_TERM]);
+    let value: std::collections::BTreeMap<String, String> = from_slice(&buffer).unwrap();
+    assert_eq!(value, map);
+}
+
+#[test]
+fn test_blob_chain() {
+    let buffer = vec![CP_BLOB_CHAIN, 2, 1, 2, 3, 3, 4, 5, 0];
+    let value: ByteBuf = from_slice(&buffer).unwrap();
+    assert_eq!(value, vec![1, 2, 3, 4, 5]);
+
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    let mut sink = Vec::new();
+    assert_eq!(deserializer.deserialize_blob_to(&mut sink).unwrap(), 5);
+    assert_eq!(sink, vec![1, 2, 3, 4, 5]);
+
+    let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    let mut buffer = Vec::new();
+    let mut serializer = Serializer::new(&mut buffer);
+    assert_eq!(serializer.serialize_blob_chain(&data[..]).unwrap(), data.len() as u64);
+    assert_eq!(buffer[0], CP_BLOB_CHAIN);
+    assert_eq!(buffer[buffer.len() - 1], 0);
+
+    let value: ByteBuf = from_slice(&buffer).unwrap();
+    assert_eq!(value.into_vec(), data);
+
+    let mut deserializer = Deserializer::from_slice(&buffer);
+    let mut sink = Vec::new();
+    deserializer.deserialize_blob_to(&mut sink).unwrap();
+    assert_eq!(sink, data);
+
+    let buffer = to_vec(&ByteBuf::from(data.clone())).unwrap();
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    let mut sink = Vec::new();
+    deserializer.deserialize_blob_to(&mut sink).unwrap();
+    assert_eq!(sink, data);
+}
+
+#[test]
+fn test_limits() {
+    // a huge declared length must fail on the missing data, not on allocation
+    let buffer = vec![CP_STRING, 0xF4, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, b'a'];
+    let mut deserializer = Deserializer::from_reader(&buffer[..]).with_max_string_len(u64::MAX).with_max_value_bytes(u64::MAX);
+    assert!(matches!(String::deserialize(&mut deserializer).unwrap_err().kind(), Error::Eof));
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_string_len(u64::MAX).with_max_value_bytes(u64::MAX);
+    assert!(matches!(String::deserialize(&mut deserializer).unwrap_err().kind(), Error::Eof));
+    // the default limits reject it up front
+    assert!(matches!(from_slice::<String>(&buffer).unwrap_err().kind(), Error::LimitExceeded { what: "string", limit: 0x100_0000 }));
+
+    let buffer = to_vec(&("abcd", ByteBuf::from(vec![1, 2, 3, 4]))).unwrap();
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_string_len(3);
+    assert!(matches!(<(String, ByteBuf)>::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "string", limit: 3 }));
+    let mut deserializer = Deserializer::from_reader(&buffer[..]).with_max_blob_len(3);
+    assert!(matches!(<(String, ByteBuf)>::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "blob", limit: 3 }));
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_string_len(4).with_max_blob_len(4).with_max_value_bytes(7);
+    assert!(matches!(<(String, ByteBuf)>::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "value", limit: 7 }));
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_value_bytes(8);
+    assert!(<(String, ByteBuf)>::deserialize(&mut deserializer).is_ok());
+
+    // the value limit applies to each value of a stream, whatever its shape
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Message {
+        Text(String),
+    }
+    let text = "x".repeat(1000);
+    let mut buffer = Vec::new();
+    for _ in 0..3 {
+        to_writer(&mut buffer, &Message::Text(text.clone())).unwrap();
+    }
+    let stream = Deserializer::from_reader(&buffer[..]).with_max_value_bytes(2500).into_iter::<Message>();
+    assert_eq!(stream.map(|item| item.unwrap().1).collect::<Vec<_>>(), [(); 3].map(|_| Message::Text(text.clone())));
+    let mut buffer = Vec::new();
+    for i in 0..3u8 {
+        to_writer(&mut buffer, &(text.clone(), i)).unwrap();
+    }
+    let stream = Deserializer::from_reader(&buffer[..]).with_max_value_bytes(2500).into_iter::<(String, u8)>();
+    assert_eq!(stream.map(|item| item.unwrap().1.1).collect::<Vec<_>>(), [0, 1, 2]);
+    // reused by hand, the deserializer can't tell where a value starts
+    let mut deserializer = Deserializer::from_reader(&buffer[..]).with_max_value_bytes(2500);
+    assert!(<(String, u8)>::deserialize(&mut deserializer).is_ok());
+    assert!(<(String, u8)>::deserialize(&mut deserializer).is_ok());
+    let err = <(String, u8)>::deserialize(&mut deserializer).unwrap_err();
+    assert!(matches!(err.kind(), Error::LimitExceeded { what: "value", limit: 2500 }));
+
+    let buffer = vec![CP_CSTRING, b'a', b'b', b'c', b'd', 0];
+    let mut deserializer = Deserializer::from_reader(&buffer[..]).with_max_string_len(3);
+    assert!(matches!(String::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "string", limit: 3 }));
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_string_len(4);
+    assert_eq!(String::deserialize(&mut deserializer).unwrap(), "abcd");
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_value_bytes(3);
+    assert!(matches!(String::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "value", limit: 3 }));
+
+    let buffer = vec![CP_BLOB_CHAIN, 2, 1, 2, 3, 3, 4, 5, 0];
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_blob_len(4);
+    assert!(matches!(ByteBuf::deserialize(&mut deserializer).unwrap_err().kind(), Error::LimitExceeded { what: "blob", limit: 4 }));
+}
+
+#[test]
+fn test_depth_limit() {
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Nested(Vec<Nested>);
+
+    fn nested(depth: usize) -> Nested {
+        (0..depth).fold(Nested(vec![]), |inner, _| Nested(vec![inner]))
+    }
+
+    let buffer = vec![CP_LIST; 100_000];
+    assert!(matches!(from_slice::<serde::de::IgnoredAny>(&buffer).unwrap_err().kind(), Error::DepthLimitExceeded(128)));
+    assert!(matches!(from_slice::<Nested>(&buffer).unwrap_err().kind(), Error::DepthLimitExceeded(128)));
+
+    assert!(matches!(to_vec(&nested(128)), Err(Error::DepthLimitExceeded(128))));
+    let buffer = to_vec(&nested(127)).unwrap();
+    assert_eq!(buffer.len(), 2 * 128);
+    assert!(from_slice::<Nested>(&buffer).is_ok());
+    let mut deserializer = Deserializer::from_slice(&buffer).with_max_depth(10);
+    assert!(matches!(Nested::deserialize(&mut deserializer).unwrap_err().kind(), Error::DepthLimitExceeded(10)));
+
+    // raised limits need a stack to match, unoptimized builds use several KiB per level
+    let deep = std::thread::Builder::new().stack_size(16 << 20).spawn(|| {
+        let mut buffer = Vec::new();
+        let mut serializer = Serializer::new(&mut buffer).with_max_depth(300);
+        nested(200).serialize(&mut serializer).unwrap();
+        let mut deserializer = Deserializer::from_slice(&buffer).with_max_depth(300);
+        assert!(Nested::deserialize(&mut deserializer).is_ok());
+    });
+    deep.unwrap().join().unwrap();
+}
+
+#[test]
+fn test_error_location() {
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Item<T> {
+        value: T,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Params<T> {
+        items: Vec<Item<T>>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Request<T> {
+        params: Params<T>,
+    }
+
+    let items = [RpcValue::Int(1), RpcValue::Int(2), RpcValue::Int(3), RpcValue::String("x".into()), RpcValue::Int(5)]
+        .into_iter()
+        .map(|value| Item { value })
+        .collect();
+    let buffer = to_vec(&Request { params: Params { items } }).unwrap();
+    let bad_value = buffer.windows(3).position(|w| w == [CP_STRING, 1, b'x']).unwrap() + 3;
+
+    let err = from_slice::<Request<u8>>(&buffer).unwrap_err();
+    assert_eq!(err.path(), Some("params.items[3].value"));
+    assert_eq!(err.offset(), Some(bad_value as u64));
+    assert!(matches!(err.kind(), Error::InvalidType { .. }));
+    assert!(err.to_string().ends_with(&format!("at offset {bad_value}, path params.items[3].value")));
+
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    let err = Request::<u8>::deserialize(&mut deserializer).unwrap_err();
+    assert_eq!(err.path(), Some("params.items[3].value"));
+    assert_eq!(err.offset(), Some(bad_value as u64));
+
+    let err = from_slice::<Request<RpcValue>>(&buffer[..buffer.len() - 3]).unwrap_err();
+    assert!(matches!(err.kind(), Error::Eof));
+    assert_eq!(err.path(), Some("params.items[5]"));
+    assert_eq!(err.offset(), Some(buffer.len() as u64 - 3));
+
+    let err = from_slice::<Vec<String>>(&[CP_LIST, CP_STRING, 1, b'a', 0x41]).unwrap_err();
+    assert_eq!(err.path(), Some("[1]"));
+    assert_eq!(err.offset(), Some(5));
+
+    // keys of earlier and nested maps don't leak into the path
+    let buffer = [
+        CP_MAP, CP_STRING, 1, b'a', CP_MAP, CP_STRING, 2, b'b', b'b', 0x01, CP_TERM,
+        CP_CSTRING, b'c', b'\\', b'\\', 0, CP_MAP, CP_CSTRING, b'd', 0, CP_STRING, 1, b'x', CP_TERM,
+        CP_TERM,
+    ];
+    type Nested = std::collections::HashMap<String, std::collections::HashMap<String, u8>>;
+    let err = from_slice::<Nested>(&buffer).unwrap_err();
+    assert_eq!(err.path(), Some("c\\.d"));
+    let err = Nested::deserialize(&mut Deserializer::from_reader(&buffer[..])).unwrap_err();
+    assert_eq!(err.path(), Some("c\\.d"));
+}
+
+#[test]
+fn test_type_errors() {
+    fn invalid_type(err: Error) -> (String, String) {
+        match err.kind() {
+            Error::InvalidType { found, expected, .. } => (found.clone(), expected.clone()),
+            e => panic!("unexpected error: {e:?}"),
+        }
+    }
+
+    let err = from_slice::<u8>(&to_vec("x").unwrap()).unwrap_err();
+    assert_eq!(invalid_type(err), ("string \"x\" (ChainPack String)".into(), "u8".into()));
+    let err = from_slice::<Vec<i32>>(&[CP_MAP, CP_TERM]).unwrap_err();
+    assert_eq!(invalid_type(err), ("map (ChainPack Map)".into(), "a sequence".into()));
+    let err = from_slice::<(i32, i32)>(&[CP_MAP, CP_TERM]).unwrap_err();
+    assert_eq!(invalid_type(err), ("ChainPack Map".into(), "a tuple".into()));
+    let err = from_slice::<(i32, i32)>(&[CP_LIST, 0x41, 0x42, 0x43, CP_TERM]).unwrap_err();
+    assert_eq!(invalid_type(err), ("ChainPack Int".into(), "the end of a tuple".into()));
+    // the ChainPack type is added exactly once, whatever the visitor's description says
+    let err = from_slice::<u8>(&to_vec("ChainPack").unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::InvalidType { chainpack_type: Some("String"), .. }));
+    assert_eq!(invalid_type(err).0, "string \"ChainPack\" (ChainPack String)");
+    let err = from_slice::<Vec<u8>>(&[CP_LIST, CP_STRING, 1, b'x', CP_TERM]).unwrap_err();
+    assert_eq!(invalid_type(err).0, "string \"x\" (ChainPack String)");
+    let err = from_slice::<i32>(&[0x84]).unwrap_err();
+    assert!(matches!(err.kind(), Error::InvalidType { chainpack_type: None, .. }));
+    assert_eq!(invalid_type(err), ("unknown type byte 0x84".into(), "a ChainPack value".into()));
+    assert_eq!(
+        from_slice::<i32>(&[0x84]).unwrap_err().to_string(),
+        "Invalid type: found unknown type byte 0x84, expected a ChainPack value at offset 1"
+    );
+
+    let err = to_vec(&serde_chainpack::cpistruct::CPIStruct(1.5f32)).unwrap_err();
+    assert!(matches!(err, Error::UnsupportedType { method: "f32", serializer: "RawBytesSerializer" }));
+    let err = to_vec(&serde_chainpack::cpistruct::CPIStruct(vec![1, 2])).unwrap_err();
+    assert!(matches!(err, Error::UnsupportedType { method: "seq", serializer: "RawBytesSerializer" }));
+    assert_eq!(err.to_string(), "Unsupported type: seq in RawBytesSerializer");
+}
+
+#[test]
+fn test_trailing_bytes() {
+    let buffer = vec![0x41, CP_STRING, 1, b'a', CP_NULL];
+    let err = from_slice::<i32>(&buffer).unwrap_err();
+    assert!(matches!(err.kind(), Error::TrailingBytes));
+    assert_eq!(err.offset(), Some(1));
+
+    let (value, rest) = from_slice_partial::<i32>(&buffer).unwrap();
+    assert_eq!((value, rest), (1, &buffer[1..]));
+    let (value, rest) = from_slice_partial::<&str>(rest).unwrap();
+    assert_eq!((value, rest), ("a", &[CP_NULL][..]));
+    let (value, rest) = from_slice_partial::<()>(rest).unwrap();
+    assert_eq!((value, rest), ((), &[][..]));
+
+    let mut deserializer = Deserializer::from_reader(&buffer[..]);
+    assert_eq!(i32::deserialize(&mut deserializer).unwrap(), 1);
+    assert!(matches!(deserializer.end().unwrap_err().kind(), Error::TrailingBytes));
+    assert_eq!(String::deserialize(&mut deserializer).unwrap(), "a");
+    <()>::deserialize(&mut deserializer).unwrap();
+    deserializer.end().unwrap();
+}
+
+#[test]
+fn test_f32() {
+    let serialized = to_vec(&1.5f32).unwrap();
+    assert_eq!(serialized, to_vec(&1.5f64).unwrap());
+    assert_eq!(from_slice::<f32>(&serialized).unwrap(), 1.5);
+    assert_eq!(from_slice::<f32>(&to_vec(&0.1f32).unwrap()).unwrap(), 0.1);
+    assert_eq!(from_slice::<f32>(&to_vec(&0.1f64).unwrap()).unwrap(), 0.1);
+    assert!(from_slice::<f32>(&to_vec(&f32::NAN).unwrap()).unwrap().is_nan());
+
+    let strict = |buffer: &[u8]| f32::deserialize(&mut Deserializer::from_reader(buffer).with_strict_f32(true));
+    assert_eq!(strict(&serialized).unwrap(), 1.5);
+    assert_eq!(strict(&to_vec(&0.1f32).unwrap()).unwrap(), 0.1);
+    assert!(strict(&to_vec(&f32::INFINITY).unwrap()).unwrap().is_infinite());
+    assert!(strict(&to_vec(&f64::NAN).unwrap()).unwrap().is_nan());
+    let err = strict(&to_vec(&0.1f64).unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::LossyF32(v) if *v == 0.1));
+    assert!(matches!(strict(&to_vec(&1e300f64).unwrap()).unwrap_err().kind(), Error::LossyF32(_)));
+}
+
+#[test]
+fn test_integer_range() {
+    fn round_trip<T>(v: T)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let serialized = to_vec(&v).unwrap();
+        assert_eq!(from_slice::<T>(&serialized).unwrap(), v);
+    }
+    round_trip(i64::MIN);
+    round_trip(i64::MAX);
+    round_trip(u64::MAX);
+    round_trip(1u64 << 63);
+    round_trip(i128::MIN);
+    round_trip(i128::MAX);
+    round_trip(u128::MAX);
+    round_trip(-(1i128 << 64));
+    round_trip(vec![i8::MIN, -1, 0, i8::MAX]);
+
+    assert_eq!(to_vec(&i64::MIN).unwrap(), [CP_INT, 0xF5, 0x80, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(to_vec(&(u64::MAX as u128)).unwrap(), to_vec(&u64::MAX).unwrap());
+    assert_eq!(from_slice::<RpcValue>(&to_vec(&u64::MAX).unwrap()).unwrap(), RpcValue::UInt(u64::MAX));
+    assert_eq!(from_slice::<RpcValue>(&to_vec(&i64::MIN).unwrap()).unwrap(), RpcValue::Int(i64::MIN));
+
+    let err = from_slice::<i64>(&to_vec(&(i64::MAX as i128 + 1)).unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::IntegerOverflow("i64")));
+    let err = from_slice::<u8>(&to_vec(&300).unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::IntegerOverflow("u8")));
+    let err = from_slice::<u32>(&to_vec(&-1).unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::IntegerOverflow("u32")));
+    assert_eq!(from_slice::<i8>(&to_vec(&-128i64).unwrap()).unwrap(), -128);
+
+    // the length nibble allows payloads wider than 64 and even 128 bits
+    let mut buffer = vec![CP_UINT, 0xF5, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+    assert!(matches!(from_slice::<u64>(&buffer).unwrap_err().kind(), Error::IntegerOverflow("u64")));
+    assert_eq!(from_slice::<u128>(&buffer).unwrap(), 1 << 64);
+    buffer = vec![CP_UINT, 0xFF, 0, 0, 0];
+    buffer.extend([0xFF; 16]);
+    assert_eq!(from_slice::<u128>(&buffer).unwrap(), u128::MAX);
+    buffer[2] = 1;
+    assert!(matches!(from_slice::<u128>(&buffer).unwrap_err().kind(), Error::IntegerOverflow("u128")));
+    assert!(matches!(from_slice::<RpcValue>(&buffer).unwrap_err().kind(), Error::IntegerOverflow("u128")));
+}
+
+#[test]
+fn test_int_keyed_maps() {
+    use std::collections::{BTreeMap, HashMap};
+    use serde_chainpack::cpistruct::CPIStruct;
+
+    let map = BTreeMap::from([(1, "a".to_string()), (-2, "b".to_string())]);
+    let serialized = to_vec(&map).unwrap();
+    assert_eq!(serialized, [CP_IMAP, CP_INT, 0x42, CP_STRING, 1, b'b', 0x41, CP_STRING, 1, b'a', CP_TERM]);
+    assert_eq!(from_slice::<BTreeMap<i64, String>>(&serialized).unwrap(), BTreeMap::from([(1, "a".into()), (-2, "b".into())]));
+    assert_eq!(from_slice::<RpcValue>(&serialized).unwrap(), RpcValue::IMap(BTreeMap::from([
+        (1, RpcValue::String("a".into())),
+        (-2, RpcValue::String("b".into())),
+    ])));
+    assert_eq!(from_slice::<CPIStruct<BTreeMap<i32, String>>>(&serialized).unwrap(), CPIStruct(map));
+
+    // unsigned keys are written as Int
+    let registers = HashMap::from([(5u32, 6u16)]);
+    let serialized = to_vec(&registers).unwrap();
+    assert_eq!(serialized, [CP_IMAP, 0x45, 0x06, CP_TERM]);
+    assert_eq!(from_slice::<HashMap<u32, u16>>(&serialized).unwrap(), registers);
+    assert_eq!(to_vec(&CPIStruct(&registers)).unwrap(), serialized);
+
+    let serialized = to_vec(&BTreeMap::<i32, i32>::new()).unwrap();
+    assert_eq!(serialized, [CP_MAP, CP_TERM]);
+    assert!(from_slice::<BTreeMap<i32, i32>>(&serialized).unwrap().is_empty());
+
+    let err = to_vec(&BTreeMap::from([(u64::MAX, 1)])).unwrap_err();
+    assert!(matches!(err, Error::IntegerOverflow("i64")));
+    let err = from_slice::<HashMap<u8, i32>>(&to_vec(&BTreeMap::from([(256, 1)])).unwrap()).unwrap_err();
+    assert!(matches!(err.kind(), Error::IntegerOverflow("u8")));
+    assert_eq!(err.path(), Some("256"));
+
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize)]
+    #[serde(untagged)]
+    enum Key {
+        Int(i32),
+        Str(&'static str),
+    }
+    let err = to_vec(&BTreeMap::from([(Key::Int(1), 1), (Key::Str("a"), 2)])).unwrap_err();
+    assert!(matches!(err, Error::MixedMapKeys));
+
+    // compound keys and the ChainPack newtypes make a plain Map
+    let map = BTreeMap::from([((1, 2), 3)]);
+    let serialized = to_vec(&map).unwrap();
+    assert_eq!(serialized, [CP_MAP, CP_LIST, 0x41, 0x42, CP_TERM, 0x43, CP_TERM]);
+    assert_eq!(from_slice::<BTreeMap<(i32, i32), i32>>(&serialized).unwrap(), map);
+    let map = BTreeMap::from([(vec![1u8, 2], 3)]);
+    assert_eq!(to_vec(&map).unwrap(), [CP_MAP, CP_LIST, 0x01, 0x02, CP_TERM, 0x43, CP_TERM]);
+
+    use serde_chainpack::{cpdecimal::CPDecimal, types::CP_DECIMAL};
+    let map = BTreeMap::from([(CPDecimal::new(15, -1), 1)]);
+    let serialized = to_vec(&map).unwrap();
+    assert_eq!(serialized[..2], [CP_MAP, CP_DECIMAL]);
+    assert_eq!(from_slice::<BTreeMap<CPDecimal, i32>>(&serialized).unwrap(), map);
+    let serialized = serde_chainpack::cpon::to_string(&map).unwrap();
+    assert_eq!(serialized, "{1.5:1}");
+
+    #[cfg(feature = "chrono")]
+    {
+        use serde_chainpack::{cpdatetime::CPDateTime, types::CP_DATETIME};
+        let map = BTreeMap::from([(CPDateTime::from_epoch_msec(1_700_000_000_000).unwrap(), 1)]);
+        let serialized = to_vec(&map).unwrap();
+        assert_eq!(serialized[..2], [CP_MAP, CP_DATETIME]);
+        assert_eq!(from_slice::<BTreeMap<CPDateTime, i32>>(&serialized).unwrap(), map);
+        assert!(serde_chainpack::cpon::to_string(&map).unwrap().starts_with("{d\""));
+    }
+}
+
+#[test]
+fn test_datetime_out_of_range() {
+    use serde_chainpack::{cpdatetime::RawDateTime, types::CP_DATETIME};
+
+    // seconds which overflow as milliseconds
+    let mut buffer = to_vec(&(((i64::MAX >> 2) << 2) | 2)).unwrap();
+    buffer[0] = CP_DATETIME;
+    for err in [
+        from_slice::<RpcValue>(&buffer).unwrap_err(),
+        from_slice::<RawDateTime>(&buffer).unwrap_err(),
+    ] {
+        assert!(err.to_string().starts_with("Invalid date/time value"), "{err}");
+    }
+    #[cfg(feature = "chrono")]
+    assert!(from_slice::<serde_chainpack::cpdatetime::CPDateTime>(&buffer).is_err());
+
+    // seconds which overflow when moved from the ChainPack epoch
+    let mut buffer = to_vec(&(((i64::MAX / 1000) << 2) | 2)).unwrap();
+    buffer[0] = CP_DATETIME;
+    assert!(from_slice::<RawDateTime>(&buffer).is_err());
+    let mut buffer = to_vec(&((i64::MAX >> 2) << 2)).unwrap();
+    buffer[0] = CP_DATETIME;
+    assert_eq!(from_slice::<RawDateTime>(&buffer).unwrap().epoch_msec, (i64::MAX >> 2) + 1517529600000);
+}
+
+#[test]
+fn test_stream_deserializer() {
+    let mut buffer = Vec::new();
+    for value in [1u32, 100, 2] {
+        to_writer(&mut buffer, &value).unwrap();
+    }
+    to_writer(&mut buffer, &"abc").unwrap();
+
+    let values: Vec<(u64, RpcValue)> = StreamDeserializer::new(&buffer[..]).collect::<Result<_, _>>().unwrap();
+    assert_eq!(values, vec![
+        (0, RpcValue::UInt(1)),
+        (1, RpcValue::UInt(100)),
+        (3, RpcValue::UInt(2)),
+        (4, RpcValue::String("abc".into())),
+    ]);
+
+    let mut stream = Deserializer::from_reader(&buffer[..buffer.len() - 1]).into_iter::<RpcValue>();
+    assert_eq!(stream.next().unwrap().unwrap(), (0, RpcValue::UInt(1)));
+    assert!(matches!(stream.nth(2).unwrap().unwrap_err().kind(), Error::Eof));
+    assert!(stream.next().is_none());
+    assert_eq!(stream.byte_offset(), buffer.len() as u64 - 1);
+
+    // the reader is not consumed beyond the values read
+    let mut reader = &buffer[..];
+    let mut stream = StreamDeserializer::<_, u32>::new(&mut reader);
+    assert_eq!(stream.next().unwrap().unwrap(), (0, 1));
+    drop(stream);
+    assert_eq!(reader.len(), buffer.len() - 1);
+
+    assert!(StreamDeserializer::<_, u32>::new(&[][..]).next().is_none());
+}